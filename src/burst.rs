@@ -0,0 +1,145 @@
+use std::fmt;
+use std::error::Error;
+use std::vec;
+
+use futures::{Future, Sink, Stream, Poll, Async, AsyncSink};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{Dialogue, MessageError, Role};
+
+/// A future which starts sending several messages at once, created via
+/// `Dialogue::send_burst`.
+///
+/// All messages are passed to the transport before it is flushed, unless the
+/// transport needs to be flushed to accept more of them. Resolves once all
+/// messages have been accepted by the transport, they might not have been
+/// flushed yet.
+pub struct BurstFuture<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> {
+    dialogue: &'d mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    messages: vec::IntoIter<Data>,
+    pending: Option<P>,
+    sent: usize,
+}
+
+impl<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> BurstFuture<'d,
+                                                                                P,
+                                                                                T,
+                                                                                SinkErr,
+                                                                                StreamErr,
+                                                                                Data,
+                                                                                R> {
+    pub(crate) fn new(dialogue: &'d mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+                      messages: Vec<Data>)
+                      -> BurstFuture<'d, P, T, SinkErr, StreamErr, Data, R> {
+        BurstFuture {
+            dialogue,
+            messages: messages.into_iter(),
+            pending: None,
+            sent: 0,
+        }
+    }
+}
+
+impl<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> Future
+    for
+    BurstFuture<'d, P, T, SinkErr, StreamErr, Data, R>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = ();
+    type Error = BurstSendError<SinkErr>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut flushed = false;
+
+        loop {
+            let result = match self.pending.take() {
+                Some(packet) => self.dialogue.start_send_packet(packet).map_err(MessageError::from),
+                None => {
+                    match self.messages.next() {
+                        Some(data) => self.dialogue.message(data),
+                        None => return Ok(Async::Ready(())),
+                    }
+                }
+            };
+
+            match result {
+                Ok(AsyncSink::Ready) => self.sent += 1,
+                Ok(AsyncSink::NotReady(packet)) => {
+                    self.pending = Some(packet);
+
+                    // The transport is full, flush it once to make room.
+                    if flushed {
+                        return Ok(Async::NotReady);
+                    }
+                    match self.dialogue.poll_complete() {
+                        Ok(Async::Ready(())) => flushed = true,
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(err) => {
+                            return Err(BurstSendError::SinkError {
+                                           sent: self.sent,
+                                           err,
+                                       })
+                        }
+                    }
+                }
+                Err(MessageError::ClosedDialogue) => {
+                    return Err(BurstSendError::ClosedDialogue { sent: self.sent })
+                }
+                Err(MessageError::ExhaustedIds) => {
+                    return Err(BurstSendError::ExhaustedIds { sent: self.sent })
+                }
+            }
+        }
+    }
+}
+
+/// The error of a `BurstFuture`. Each variant reports how many messages had
+/// been accepted by the transport before the error occurred.
+#[derive(Debug)]
+pub enum BurstSendError<SinkErr> {
+    /// The `Dialogue` has been closed.
+    ClosedDialogue {
+        /// The number of messages accepted before the error.
+        sent: usize,
+    },
+    /// All ids available to this side of the `Dialogue` are in use.
+    ExhaustedIds {
+        /// The number of messages accepted before the error.
+        sent: usize,
+    },
+    /// Flushing the transport to make room for more messages failed.
+    SinkError {
+        /// The number of messages accepted before the error.
+        sent: usize,
+        /// The error emitted by the transport.
+        err: SinkErr,
+    },
+}
+
+impl<SinkErr: fmt::Display> fmt::Display for BurstSendError<SinkErr> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BurstSendError::ClosedDialogue { sent } => {
+                write!(fmt, "ClosedDialogue after sending {} messages", sent)
+            }
+            BurstSendError::ExhaustedIds { sent } => {
+                write!(fmt, "ExhaustedIds after sending {} messages", sent)
+            }
+            BurstSendError::SinkError { sent, ref err } => {
+                write!(fmt, "SinkError after sending {} messages: {}", sent, err)
+            }
+        }
+    }
+}
+
+impl<SinkErr: Error + 'static> Error for BurstSendError<SinkErr> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            BurstSendError::ClosedDialogue { .. } |
+            BurstSendError::ExhaustedIds { .. } => None,
+            BurstSendError::SinkError { ref err, .. } => Some(err),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::mem;
+
+use futures::{Sink, Stream, Poll, StartSend, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A `SubDuplex` whose received items are emitted in batches, created via
+/// `SubDuplex::chunks`.
+///
+/// Each item of the `Stream` implementation is a `Vec` of up to `size` items
+/// received on the duplex. If the duplex ends while items are buffered, they
+/// are emitted as a final, shorter chunk. If the duplex errors while items are
+/// buffered, they are emitted as a shorter chunk, followed by the error.
+///
+/// The `Sink` implementation is forwarded to the wrapped `SubDuplex`.
+pub struct ChunkedSubDuplex<'ps,
+                            P: 'ps,
+                            T: 'ps,
+                            SinkErr: 'ps,
+                            StreamErr: 'ps,
+                            Data: 'ps,
+                            R: 'ps,
+                            SubDuplexType: 'static>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+    items: Vec<Data>,
+    size: usize,
+    err: Option<SubStreamError<Data>>,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    ChunkedSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+    /// Creates a new `ChunkedSubDuplex`. Panics if `size` is zero.
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+                      size: usize)
+                      -> ChunkedSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        assert!(size > 0);
+
+        ChunkedSubDuplex {
+            duplex,
+            items: Vec::with_capacity(size),
+            size,
+            err: None,
+        }
+    }
+
+    /// Returns a reference to the wrapped `SubDuplex`.
+    pub fn get_ref(&self) -> &SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        &self.duplex
+    }
+
+    /// Returns a mutable reference to the wrapped `SubDuplex`.
+    pub fn get_mut(&mut self)
+                   -> &mut SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        &mut self.duplex
+    }
+
+    fn take_items(&mut self) -> Vec<Data> {
+        let size = self.size;
+        mem::replace(&mut self.items, Vec::with_capacity(size))
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static> Stream
+    for
+    ChunkedSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Vec<Data>;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(err) = self.err.take() {
+            return Err(err);
+        }
+
+        loop {
+            match self.duplex.poll() {
+                Ok(Async::Ready(Some(item))) => {
+                    self.items.push(item);
+                    if self.items.len() >= self.size {
+                        return Ok(Async::Ready(Some(self.take_items())));
+                    }
+                }
+                Ok(Async::Ready(None)) => {
+                    if self.items.is_empty() {
+                        return Ok(Async::Ready(None));
+                    } else {
+                        return Ok(Async::Ready(Some(self.take_items())));
+                    }
+                }
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    if self.items.is_empty() {
+                        return Err(err);
+                    } else {
+                        self.err = Some(err);
+                        return Ok(Async::Ready(Some(self.take_items())));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static> Sink
+    for
+    ChunkedSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type SinkItem = Data;
+    type SinkError = ClosedDialogue;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.duplex.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.close()
+    }
+}
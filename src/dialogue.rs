@@ -1,13 +1,32 @@
-// TODO closing/aborting a Dialogue
-
 use std::marker::PhantomData;
 use std::fmt;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
 
-use futures::{Future, Sink, Stream, Poll, StartSend};
+use futures::{Future, Sink, Stream, Poll, StartSend, Async, AsyncSink};
+use futures::task::{self, Task};
+use futures::executor::{self, Notify, NotifyHandle};
 
 use packet::{PacketWritable, PacketReadable, PacketId, PacketType};
 use transport_error::TransportError;
+use chunks::ChunkedSubDuplex;
+use recv_exactly::{RecvExactly, ExpectExactly};
+use on_close::OnCloseSubDuplex;
+use fanout::FanoutFuture;
+use filter::FilteredDialogue;
+use burst::BurstFuture;
+use validation::{ValidatedDialogue, PacketValidator, ValidationError};
+use writer::SubDuplexWriter;
+use sub_sink::SubSink;
+use sub_stream::SubStream;
+use sub_reduce_sink::SubReduceSink;
+use sub_reduce_stream::SubReduceStream;
+use sub_fold_sink::SubFoldSink;
+use sub_fold_stream::SubFoldStream;
+use timeout::{TimedResponse, Timer};
 
 /// Type-Level indicator for whether a `Dialogue` takes the server or the client
 /// role. This information determines behaviour during the closing handshake.
@@ -38,13 +57,75 @@ impl Role for Client {
 ///
 /// Incoming packets are emitted via the `Stream` implementation of `Dialogue`.
 /// Packets can be sent via the corresponding methods of the struct.
+///
+/// Requests and duplexes initiated by a server use even ids, those initiated
+/// by a client use odd ids, so the ids of both sides never collide. Ids are
+/// counted upwards and wrap around at `PacketId::MAX`, skipping ids that are
+/// still in use. If all ids of a side are in use, sending new messages,
+/// requests and duplexes fails with `ExhaustedIds`.
 pub struct Dialogue<P, T, SinkErr, StreamErr, Data, R> {
     transport: T,
-    packet_type: PhantomData<P>,
-    sink_err_type: PhantomData<SinkErr>,
-    stream_err_type: PhantomData<StreamErr>,
-    data_type: PhantomData<Data>,
-    role_type: PhantomData<R>,
+    // The `Dialogue` never owns a role, so the marker uses `fn() -> R`: This
+    // keeps the struct covariant, and does not make `Send`, `Sync` or drop
+    // checking depend on the role.
+    role_type: PhantomData<fn() -> R>,
+    reading_paused: bool,
+    paused_task: Option<Task>,
+    peeked: Option<P>,
+    // Hands out the ids of the packets sent by this side.
+    ids: PacketIdAllocator,
+    // Requests and duplexes initiated by this side, by id.
+    outgoing: HashMap<PacketId, OutRoute<Data>>,
+    // Requests and duplexes initiated by the peer, by id.
+    incoming: HashMap<PacketId, InRoute<Data>>,
+    // Incoming packets with fresh ids, not yet emitted by the `Stream`
+    // implementation.
+    fresh: VecDeque<P>,
+    // Checks every incoming packet before it is routed, set by
+    // `with_packet_validation`.
+    validator: Option<Box<dyn PacketValidator<P> + Send>>,
+    // The errors of rejected packets, not yet emitted by the `Stream`
+    // implementation.
+    invalid: VecDeque<ValidationError>,
+    // The id of the duplex initiated by the `DuplexInitial` packet emitted
+    // last by the `Stream` implementation, until it is accepted. It is
+    // discarded when the stream is polled again.
+    unaccepted: Option<PacketId>,
+    // Creates packets, stored so that the `Drop` implementations of the handles
+    // can create packets although they have no trait bounds.
+    new_packet: fn(Option<Data>, PacketId, PacketType) -> P,
+    // Packets which have been sent but not yet accepted by the transport, they
+    // are passed to the transport before any other packet. Each packet is
+    // stored with its id and its scheduling priority, see `enqueue`.
+    queued: VecDeque<(Option<u8>, PacketId, P)>,
+    // Whether this side sent its close message, see `close`.
+    close_sent: bool,
+    // Set once a client sent its close message, it must not send any packets
+    // afterwards.
+    sending_closed: bool,
+    // Whether the server answered the client's close message.
+    close_answered: bool,
+    // Whether this side sent an abort message, see `abort`.
+    abort_sent: bool,
+    // Whether the peer sent its final close message: For a server, the
+    // client's close message, for a client, the server's answer to it.
+    peer_closed: bool,
+    // Whether a client received a close message from the server before
+    // sending its own.
+    close_requested: bool,
+    // The task of a server waiting in `close` for its requests and duplexes
+    // to finish, notified whenever a route is removed.
+    closing_task: Option<Task>,
+    // Whether the peer aborted the dialogue.
+    peer_aborted: bool,
+    // Set once the dialogue has been closed or aborted, or the transport
+    // failed. No more packets are sent or read.
+    closed: bool,
+    // The error which closed the dialogue, until it is emitted by the `Stream`
+    // implementation.
+    error: Option<TransportError<SinkErr, StreamErr>>,
+    // The timeout of the responses created by `timed_request`, if any.
+    default_request_timeout: Option<Duration>,
 }
 
 impl<P, T, SinkErr, StreamErr, Data, R> Dialogue<P, T, SinkErr, StreamErr, Data, R>
@@ -53,58 +134,816 @@ impl<P, T, SinkErr, StreamErr, Data, R> Dialogue<P, T, SinkErr, StreamErr, Data,
           R: Role
 {
     /// Creates a new `Dialogue` over the given transport.
+    ///
+    /// The `Role` determines the ids of the packets sent by this side: a
+    /// `Server` uses even ids and a `Client` uses odd ids.
     pub fn new(transport: T) -> Dialogue<P, T, SinkErr, StreamErr, Data, R> {
-        unimplemented!()
+        Dialogue {
+            transport,
+            role_type: PhantomData,
+            reading_paused: false,
+            paused_task: None,
+            peeked: None,
+            ids: PacketIdAllocator::new(if R::is_server() { 0 } else { 1 }),
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+            fresh: VecDeque::new(),
+            validator: None,
+            invalid: VecDeque::new(),
+            unaccepted: None,
+            new_packet: new_packet::<P>,
+            queued: VecDeque::new(),
+            close_sent: false,
+            sending_closed: false,
+            close_answered: false,
+            abort_sent: false,
+            peer_closed: false,
+            close_requested: false,
+            closing_task: None,
+            peer_aborted: false,
+            closed: false,
+            error: None,
+            default_request_timeout: None,
+        }
+    }
+
+    /// Passes the queued packets to the transport.
+    fn send_queued(&mut self) -> Poll<(), SinkErr> {
+        while let Some((priority, id, packet)) = self.queued.pop_front() {
+            if let AsyncSink::NotReady(packet) = self.transport.start_send(packet)? {
+                self.queued.push_front((priority, id, packet));
+                return Ok(Async::NotReady);
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Like `read_packet`, but a transport error closes the dialogue, so that
+    /// the `Stream` implementation emits it later. Used by the handles for
+    /// requests and duplexes, which can't emit transport errors themselves.
+    fn poll_read(&mut self) -> Async<bool> {
+        match self.read_packet() {
+            Ok(ready_or_not) => ready_or_not,
+            Err(err) => {
+                self.fail(TransportError::StreamError(err));
+                Async::Ready(false)
+            }
+        }
+    }
+
+    /// Closes the dialogue because of an error of the transport.
+    fn fail(&mut self, err: TransportError<SinkErr, StreamErr>) {
+        self.closed = true;
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+
+    /// Reads a single packet from the transport and routes it. Resolves to
+    /// `false` if no more packets will be read, because the transport stream
+    /// ended or the peer sent its final close message.
+    ///
+    /// While reading is paused, this returns `NotReady` and stores the current
+    /// task, to be notified by `resume_reading`.
+    fn read_packet(&mut self) -> Poll<bool, StreamErr> {
+        if self.closed || self.peer_closed {
+            return Ok(Async::Ready(false));
+        }
+
+        if self.reading_paused {
+            self.paused_task = Some(task::current());
+            return Ok(Async::NotReady);
+        }
+
+        match self.transport.poll()? {
+            Async::Ready(Some(packet)) => {
+                let validation = match self.validator {
+                    Some(ref validator) => validator.validate(&packet),
+                    None => Ok(()),
+                };
+
+                match validation {
+                    Ok(()) => self.route(packet),
+                    Err(err) => {
+                        self.drop_invalid(&packet);
+                        self.invalid.push_back(err);
+                    }
+                }
+                Ok(Async::Ready(true))
+            }
+            Async::Ready(None) => {
+                // Without a transport stream, the dialogue can't continue.
+                self.closed = true;
+                Ok(Async::Ready(false))
+            }
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+
+    /// Passes an incoming packet to the request or duplex it belongs to, or
+    /// buffers it for the `Stream` implementation if it has a fresh id.
+    ///
+    /// Packets for requests and duplexes that have already been dropped are
+    /// discarded.
+    fn route(&mut self, packet: P) {
+        let id = packet.get_id();
+
+        match packet.get_type() {
+            PacketType::Message => {
+                if !packet.is_empty() {
+                    self.fresh.push_back(packet);
+                } else if id == ABORT_ID {
+                    self.peer_aborted = true;
+                    self.closed = true;
+                    self.clear_routes();
+                } else if R::is_server() || id == CLOSE_ANSWER_ID {
+                    // A client only stops reading on the server's answer,
+                    // not on a close request crossing its own close message.
+                    self.peer_closed = true;
+                } else {
+                    self.close_requested = true;
+                }
+            }
+            PacketType::Request => {
+                if packet.is_empty() {
+                    if let Some(&mut InRoute::Request { ref mut cancelled }) =
+                        self.incoming.get_mut(&id) {
+                        *cancelled = true;
+                    } else {
+                        // The request may not have been emitted yet, then it is
+                        // never emitted at all.
+                        let is_cancelled = |packet: &P| {
+                            packet.get_type() == PacketType::Request && packet.get_id() == id
+                        };
+                        self.fresh.retain(|packet| !is_cancelled(packet));
+                        if matches!(self.peeked, Some(ref packet) if is_cancelled(packet)) {
+                            self.peeked = None;
+                        }
+                    }
+                } else if !self.incoming.contains_key(&id) {
+                    self.fresh.push_back(packet);
+                }
+                // Otherwise, the id belongs to a live request or duplex of the
+                // peer, so the packet is a duplicate and discarded.
+            }
+            PacketType::Response => {
+                if let Some(&mut OutRoute::Response(ref mut response)) =
+                    self.outgoing.get_mut(&id) {
+                    *response = Some(packet.get_data());
+                }
+            }
+            PacketType::DuplexInitial => {
+                // A duplicate id must not replace the route of a live duplex.
+                if let Entry::Vacant(entry) = self.incoming.entry(id) {
+                    // Register the duplex right away, so that packets arriving
+                    // before `packet_as_sub_duplex` is called are not lost. The
+                    // route is discarded if the duplex is not accepted.
+                    entry.insert(InRoute::Duplex(DuplexRoute::new()));
+                    self.fresh.push_back(packet);
+                }
+            }
+            PacketType::DuplexRequest |
+            PacketType::DuplexRequestEnd => {
+                if let Some(&mut InRoute::Duplex(ref mut duplex)) = self.incoming.get_mut(&id) {
+                    duplex.receive(packet);
+                }
+            }
+            PacketType::DuplexResponse |
+            PacketType::DuplexResponseEnd => {
+                if let Some(&mut OutRoute::Duplex(ref mut duplex)) = self.outgoing.get_mut(&id) {
+                    duplex.receive(packet);
+                }
+            }
+        }
+    }
+
+    /// Drops a packet rejected by the validator. For a `DuplexInitial` packet,
+    /// this side's half of the duplex is ended right away, so that the peer
+    /// does not wait for it.
+    fn drop_invalid(&mut self, packet: &P) {
+        let id = packet.get_id();
+
+        if packet.get_type() == PacketType::DuplexInitial && !self.incoming.contains_key(&id) &&
+           self.may_send() {
+            self.enqueue(None, id, PacketType::DuplexResponseEnd);
+        }
     }
 
     /// Gracefully shuts down the `Dialogue`.
+    ///
+    /// A client sends a close message (a `Message` without data) and does not
+    /// send any more packets afterwards. It keeps routing incoming packets
+    /// until the server answers with its own close message, then closes the
+    /// transport.
+    ///
+    /// A server which has not received the client's close message yet asks the
+    /// client to close by sending a close message, and continues to operate
+    /// normally until the client's close message arrives. It then waits until
+    /// all requests and duplexes of both sides are done, answers with another
+    /// close message and closes the transport. Incoming requests and duplexes
+    /// only finish once they have been emitted by the `Stream` implementation,
+    /// so it must still be polled while closing.
     pub fn close(&mut self) -> Poll<(), TransportError<SinkErr, StreamErr>> {
-        unimplemented!()
+        if self.closed {
+            return self.closed_result();
+        }
+
+        if !self.close_sent && (!R::is_server() || !self.peer_closed) {
+            self.enqueue(None, CLOSE_ID, PacketType::Message);
+            self.close_sent = true;
+            self.sending_closed = !R::is_server();
+        }
+
+        loop {
+            match self.poll_complete() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => return Err(TransportError::SinkError(err)),
+            }
+
+            if self.peer_closed {
+                break;
+            }
+
+            match self.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => {
+                    if self.closed {
+                        // The transport stream ended or errored without a close
+                        // message.
+                        return self.closed_result();
+                    }
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        if R::is_server() && !self.close_answered {
+            if !self.outgoing.is_empty() || !self.incoming.is_empty() {
+                self.closing_task = Some(task::current());
+                return Ok(Async::NotReady);
+            }
+
+            self.enqueue(None, CLOSE_ANSWER_ID, PacketType::Message);
+            self.close_answered = true;
+        }
+
+        self.close_transport()
+            .map_err(TransportError::SinkError)
+    }
+
+    /// Terminates the `Dialogue` without a proper handshake. An abort message
+    /// is sent to the peer, then this side of the `Dialogue` is terminated
+    /// immediately without waiting for any confirmation. This is the only
+    /// packet a client sends after its close message.
+    ///
+    /// All buffered incoming packets are dropped, and all requests and
+    /// duplexes fail. The peer can tell the difference to a regular close via
+    /// `is_aborted_by_peer`.
+    pub fn abort(&mut self) -> Poll<(), SinkErr> {
+        if self.closed {
+            return Ok(Async::Ready(()));
+        }
+
+        if !self.abort_sent {
+            self.enqueue(None, ABORT_ID, PacketType::Message);
+            self.abort_sent = true;
+            self.sending_closed = true;
+        }
+
+        let result = self.close_transport();
+        if self.closed {
+            self.clear_routes();
+        }
+        result
+    }
+
+    /// Returns whether the peer aborted the dialogue rather than closing it
+    /// regularly.
+    pub fn is_aborted_by_peer(&self) -> bool {
+        self.peer_aborted
+    }
+
+    /// Returns whether the dialogue has been closed or aborted, or failed
+    /// because of a transport error. No more packets are sent or read then.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns whether closing (or aborting) the dialogue has started, but has
+    /// not been completed yet: This side sent its close or abort message, or
+    /// the peer sent its final close message.
+    ///
+    /// A server which merely asked the client to close is not closing yet, see
+    /// `is_close_requested` for the client's side of that.
+    pub fn is_closing(&self) -> bool {
+        let close_sent = self.close_sent && !R::is_server();
+        !self.closed && (close_sent || self.abort_sent || self.peer_closed)
+    }
+
+    /// Drops all routes and buffered incoming packets, after the dialogue has
+    /// been aborted by either side.
+    fn clear_routes(&mut self) {
+        self.outgoing.clear();
+        self.incoming.clear();
+        self.fresh.clear();
+        self.peeked = None;
+        self.unaccepted = None;
     }
 
-    /// Terminates the `Dialogue` without a proper handshake. Termination is
-    /// signalled to the peer, then this side of the `Dialogue` is terminated
-    /// immediately without waiting for any confirmation.
-    pub fn abort(&mut self) -> Poll<(), TransportError<SinkErr, StreamErr>> {
-        unimplemented!()
+    /// Flushes all packets, then closes the transport and the dialogue.
+    fn close_transport(&mut self) -> Poll<(), SinkErr> {
+        match self.poll_complete() {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => return Err(err),
+        }
+
+        match self.transport.close() {
+            Ok(Async::Ready(())) => {
+                self.closed = true;
+                Ok(Async::Ready(()))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                self.closed = true;
+                Err(err)
+            }
+        }
+    }
+
+    /// The result of closing an already closed dialogue: The error that
+    /// closed it if it has not been emitted yet.
+    fn closed_result(&mut self) -> Poll<(), TransportError<SinkErr, StreamErr>> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(Async::Ready(())),
+        }
+    }
+
+    /// Keeps the duplex initiated by the `DuplexInitial` packet emitted last by
+    /// the `Stream` implementation, instead of discarding it when the stream is
+    /// polled again.
+    pub(crate) fn keep_unaccepted(&mut self) {
+        self.unaccepted = None;
+    }
+
+    /// Drops a packet emitted by the `Stream` implementation which is not
+    /// handed to the application. A request is declined with an empty
+    /// response. The duplex initiated by a `DuplexInitial` packet is discarded
+    /// right away, instead of once the stream is polled again.
+    pub(crate) fn reject(&mut self, packet: &P) {
+        let id = packet.get_id();
+
+        match packet.get_type() {
+            PacketType::Request if self.may_send() => {
+                self.enqueue(None, id, PacketType::Response);
+            }
+            PacketType::DuplexInitial => {
+                if self.unaccepted == Some(id) {
+                    self.unaccepted = None;
+                }
+                self.discard_duplex(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes the route of an incoming duplex which has not been accepted, and
+    /// ends this side's half of it, so that the peer does not wait for it.
+    pub(crate) fn discard_duplex(&mut self, id: PacketId) {
+        if let Some(&InRoute::Duplex(_)) = self.incoming.get(&id) {
+            self.remove_incoming(id);
+
+            if self.may_send() {
+                self.enqueue(None, id, PacketType::DuplexResponseEnd);
+            }
+        }
+    }
+
+    /// Remembers an emitted `DuplexInitial` packet, so that its duplex can be
+    /// discarded if it is not accepted before the stream is polled again.
+    fn emit(&mut self, packet: P) -> P {
+        if packet.get_type() == PacketType::DuplexInitial {
+            self.unaccepted = Some(packet.get_id());
+        }
+        packet
+    }
+
+    /// Returns whether the server asked this client to close the dialogue.
+    ///
+    /// The server continues to operate normally after asking, the client
+    /// should call `close` once it is done. This is always `false` for a
+    /// server.
+    pub fn is_close_requested(&self) -> bool {
+        self.close_requested
+    }
+
+    /// Returns the number of ids currently in use by requests and duplexes
+    /// initiated by this side.
+    ///
+    /// An id is in use from the call to `request` or `sub_duplex` until the
+    /// corresponding `Response` or `SubDuplex` is done or dropped. Ids are only
+    /// handed out again once they are no longer in use.
+    ///
+    /// Since `Response`s and `SubDuplex`es borrow the `Dialogue` mutably, at
+    /// most one of them exists at a time. In practice, this is only ever 0 or
+    /// 1, unless handles are leaked via `mem::forget`.
+    pub fn active_ids(&self) -> usize {
+        self.ids.in_use()
+    }
+
+    /// Returns the id counter of this side, i.e. the id the next request or
+    /// duplex will get unless it is still in use.
+    ///
+    /// The counter wraps around after `PacketId::MAX`, ids which are still in
+    /// use are skipped then.
+    pub fn request_id_counter(&self) -> PacketId {
+        self.ids.next
+    }
+
+    /// Returns the number of ids that can still be handed out by this side,
+    /// before `request` and friends fail with `ExhaustedIds`.
+    pub fn free_id_count(&self) -> usize {
+        self.ids.limit - self.ids.in_use()
+    }
+
+    /// Returns whether packets are queued in the `Dialogue`, waiting for the
+    /// transport to accept them.
+    ///
+    /// If this is `true`, `poll_complete` must be called to make progress.
+    /// Packets already handed to the transport are not taken into account, the
+    /// transport's own `poll_complete` might still need to be called.
+    pub fn needs_flush(&self) -> bool {
+        !self.queued.is_empty()
     }
 
     /// After starting sending packets via `message`, `request` or `duplex`
     /// this must be called to ensure that the packets have been written to the
-    /// underlying transport. This simply delegates to `transport.poll_complete()`.
+    /// underlying transport. This delegates to `transport.poll_complete()`, an
+    /// error closes the dialogue.
     pub fn poll_complete(&mut self) -> Poll<(), SinkErr> {
-        self.transport.poll_complete()
+        let result = match self.send_queued() {
+            Ok(Async::Ready(())) => self.transport.poll_complete(),
+            not_ready_or_err => not_ready_or_err,
+        };
+
+        if result.is_err() {
+            self.closed = true;
+        }
+        result
+    }
+
+    /// Like `poll_complete`, but does not register the current task for
+    /// notification, so it can also be called outside of a task.
+    ///
+    /// Returns `Ok(true)` if all packets have been written to the underlying
+    /// transport, and `Ok(false)` if the transport is not ready yet.
+    pub fn try_poll_complete(&mut self) -> Result<bool, TransportError<SinkErr, StreamErr>> {
+        let notify = NotifyHandle::from(Arc::new(NoopNotify));
+
+        let mut spawn = executor::spawn(self);
+
+        match spawn.poll_fn_notify(&notify, 0, |dialogue| dialogue.poll_complete()) {
+            Ok(Async::Ready(())) => Ok(true),
+            Ok(Async::NotReady) => Ok(false),
+            Err(err) => Err(TransportError::SinkError(err)),
+        }
+    }
+
+    /// Stops reading from the underlying transport until `resume_reading` is
+    /// called.
+    ///
+    /// While paused, polling the `Dialogue`'s `Stream` implementation returns
+    /// `NotReady` instead of reading new packets, so the transport can apply
+    /// its own backpressure to the peer. Sending packets and flushing via
+    /// `poll_complete` are not affected.
+    pub fn pause_reading(&mut self) {
+        self.reading_paused = true;
+    }
+
+    /// Resumes reading from the underlying transport after a call to
+    /// `pause_reading`, notifying the task that polled the `Dialogue` while it
+    /// was paused.
+    pub fn resume_reading(&mut self) {
+        self.reading_paused = false;
+
+        if let Some(task) = self.paused_task.take() {
+            task.notify();
+        }
+    }
+
+    /// Returns whether reading from the transport is currently paused.
+    pub fn is_reading_paused(&self) -> bool {
+        self.reading_paused
+    }
+
+    /// Peeks at the next incoming packet with a fresh id, without consuming it.
+    ///
+    /// Resolves to the `PacketType` and `PacketId` of the packet that the next
+    /// call to `poll` will emit, or to `None` if the stream has ended. Routing
+    /// of packets for existing requests and duplexes still happens while
+    /// peeking, but no handles are created.
+    pub fn peek_event(&mut self)
+                      -> Poll<Option<(PacketType, PacketId)>, TransportError<SinkErr, StreamErr>> {
+        if let Some(ref packet) = self.peeked {
+            return Ok(Async::Ready(Some((packet.get_type(), packet.get_id()))));
+        }
+
+        match self.poll()? {
+            Async::Ready(Some(packet)) => {
+                let event = (packet.get_type(), packet.get_id());
+                // The packet is emitted (again) by the next call to `poll`.
+                self.unaccepted = None;
+                self.peeked = Some(packet);
+                Ok(Async::Ready(Some(event)))
+            }
+            Async::Ready(None) => Ok(Async::Ready(None)),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+
+    /// Returns a future which receives exactly `n` packets from the `Stream`
+    /// implementation of this `Dialogue`.
+    ///
+    /// The future errors if the stream ends before `n` packets were received.
+    pub fn recv_exactly(&mut self,
+                        n: usize)
+                        -> RecvExactly<'_, P, T, SinkErr, StreamErr, Data, R> {
+        RecvExactly::new(self, n)
+    }
+
+    /// Converts this into a `FilteredDialogue`, whose `Stream` implementation
+    /// only emits the incoming packets for which `f` returns `true`.
+    pub fn recv_filter<F>(self, f: F) -> FilteredDialogue<P, T, SinkErr, StreamErr, Data, R, F>
+        where F: Fn(&P) -> bool
+    {
+        FilteredDialogue::new(self, f)
+    }
+
+    /// Converts this into a `ValidatedDialogue`, which checks each incoming
+    /// packet with the given `PacketValidator` before routing it.
+    pub fn with_packet_validation<V>(mut self,
+                                     validator: V)
+                                     -> ValidatedDialogue<P, T, SinkErr, StreamErr, Data, R, V>
+        where V: PacketValidator<P> + Send + 'static
+    {
+        self.validator = Some(Box::new(validator));
+        ValidatedDialogue::new(self)
     }
 
     /// Start sending the given data as a message.
     ///
+    /// If the transport is not ready, the packet is handed back and the data
+    /// can be retrieved via its `get_data` method. If the transport errors, the
+    /// dialogue is closed and the error is emitted by its `Stream`
+    /// implementation.
+    ///
+    /// If all ids available to this side are in use, `MessageError::ExhaustedIds`
+    /// is returned.
+    ///
     /// You have to call poll_complete to actually send the packet.
-    pub fn message(&mut self, data: Data) -> StartSend<P, ClosedDialogue> {
-        unimplemented!()
+    pub fn message(&mut self, data: Data) -> StartSend<P, MessageError> {
+        // Messages are not routed, so their id does not need to stay reserved.
+        let id = self.ids.alloc()?;
+        self.ids.free(id);
+
+        Ok(self.start_send_packet(new_packet(Some(data), id, PacketType::Message))?)
+    }
+
+    /// Returns a future which starts sending all the given data as messages,
+    /// passing them to the transport before flushing it.
+    ///
+    /// You have to call poll_complete to actually send the packets.
+    pub fn send_burst(&mut self,
+                      messages: Vec<Data>)
+                      -> BurstFuture<'_, P, T, SinkErr, StreamErr, Data, R> {
+        BurstFuture::new(self, messages)
+    }
+
+    /// Starts sending a packet with the given data, id and type. If the
+    /// transport does not accept the packet, it is queued instead, so at most
+    /// one packet is buffered in addition to the transport.
+    fn start_send_data(&mut self,
+                       data: Data,
+                       id: PacketId,
+                       packet_type: PacketType)
+                       -> StartSend<Data, ClosedDialogue> {
+        if !self.may_send() {
+            return Err(ClosedDialogue);
+        }
+
+        let result = match self.send_queued() {
+            Ok(Async::Ready(())) => {
+                self.transport
+                    .start_send(new_packet(Some(data), id, packet_type))
+            }
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(data)),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(AsyncSink::Ready) => Ok(AsyncSink::Ready),
+            Ok(AsyncSink::NotReady(packet)) => {
+                self.enqueue_packet(packet, id, Some(packet_type.scheduling_priority()));
+                Ok(AsyncSink::Ready)
+            }
+            Err(err) => {
+                self.fail(TransportError::SinkError(err));
+                Err(ClosedDialogue)
+            }
+        }
+    }
+
+    /// Starts sending a packet whose id and type have already been set.
+    pub(crate) fn start_send_packet(&mut self, packet: P) -> StartSend<P, ClosedDialogue> {
+        if !self.may_send() {
+            return Err(ClosedDialogue);
+        }
+
+        let result = match self.send_queued() {
+            Ok(Async::Ready(())) => self.transport.start_send(packet),
+            Ok(Async::NotReady) => return Ok(AsyncSink::NotReady(packet)),
+            Err(err) => Err(err),
+        };
+
+        result.map_err(|err| {
+                           self.fail(TransportError::SinkError(err));
+                           ClosedDialogue
+                       })
     }
 
     /// Start sending the given dataas a request.
     ///
-    /// If sending fails, the returned `Response` `Future` yields an error.
+    /// If sending fails, the returned `Response` `Future` yields an error. If
+    /// all ids available to this side are in use, `ExhaustedIds` is returned
+    /// instead of a `Response`.
+    ///
+    /// The `Response` waits until the peer answers. Use `Response::with_deadline`
+    /// or `Response::with_timeout` to give up earlier, or `timed_request` to
+    /// apply the default request timeout.
     ///
     /// You have to call poll_complete to actually send the packet.
-    pub fn request(&mut self, data: Data) -> Response<P, T, SinkErr, StreamErr, Data, R> {
-        unimplemented!()
+    #[allow(clippy::type_complexity)]
+    pub fn request(&mut self,
+                   data: Data)
+                   -> Result<Response<'_, P, T, SinkErr, StreamErr, Data, R>, ExhaustedIds> {
+        let id = self.ids.alloc()?;
+
+        if self.may_send() {
+            self.enqueue(Some(data), id, PacketType::Request);
+            self.outgoing.insert(id, OutRoute::Response(None));
+        } else {
+            // The `Response` errors without a route, so the id is not needed.
+            self.ids.free(id);
+        }
+
+        Ok(Response {
+               ps: self,
+               id,
+               resolved: false,
+           })
+    }
+
+    /// Sets the timeout of all requests started via `timed_request` from now
+    /// on. Plain `request`s are not affected.
+    pub fn set_default_request_timeout(&mut self, timeout: Duration) {
+        self.default_request_timeout = Some(timeout);
+    }
+
+    /// Like `request`, but the returned `TimedResponse` gives up once the
+    /// default request timeout has passed, using a delay created by the
+    /// `timer`. If no default has been set via `set_default_request_timeout`,
+    /// it waits until the peer answers.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn timed_request<Ti>(&mut self,
+                             data: Data,
+                             timer: &Ti)
+                             -> Result<TimedResponse<'_, P, T, SinkErr, StreamErr, Data, R, Ti::Delay>,
+                                       ExhaustedIds>
+        where Ti: Timer
+    {
+        let delay = self.default_request_timeout
+            .map(|timeout| timer.delay_until(timer.now() + timeout));
+        Ok(TimedResponse::new(self.request(data)?, delay))
+    }
+
+    /// Sends a request with the given data to each of the `peers`, and returns
+    /// a future which collects all responses in the order of the peers.
+    ///
+    /// The requests are flushed by the returned future.
+    pub fn fanout_request<'a>(data: Data,
+                              peers: &'a mut [Dialogue<P, T, SinkErr, StreamErr, Data, R>])
+                              -> FanoutFuture<'a, P, T, SinkErr, StreamErr, Data, R>
+        where Data: Clone
+    {
+        FanoutFuture::new(peers
+                              .iter_mut()
+                              .map(|peer| peer.request(data.clone()))
+                              .collect())
     }
 
     /// Start sending the given data as a duplex.
     ///
     /// If sending fails, the returned `SubDuplex`'s `Stream` and `Sink`
     /// implementations directly yield errors since the dialogue closed (erronously).
+    /// If all ids available to this side are in use, `ExhaustedIds` is returned
+    /// instead of a `SubDuplex`.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn sub_duplex
+        (&mut self,
+         data: Data)
+         -> Result<SubDuplex<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex>, ExhaustedIds> {
+        let id = self.ids.alloc()?;
+
+        if self.may_send() {
+            self.enqueue(Some(data), id, PacketType::DuplexInitial);
+            self.outgoing
+                .insert(id, OutRoute::Duplex(DuplexRoute::new()));
+        } else {
+            // The `SubDuplex` errors without a route, so the id is not needed.
+            self.ids.free(id);
+        }
+
+        Ok(SubDuplex::new(self, id, true))
+    }
+
+    /// Start sending the given data as a duplex in which only this side sends
+    /// items, see `SubSink`.
     ///
     /// You have to call poll_complete to actually send the packet.
-    pub fn sub_duplex(&mut self,
-                      data: Data)
-                      -> SubDuplex<P, T, SinkErr, StreamErr, Data, OutSubDuplex, R> {
-        unimplemented!()
+    #[allow(clippy::type_complexity)]
+    pub fn sub_sink
+        (&mut self,
+         data: Data)
+         -> Result<SubSink<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex>, ExhaustedIds> {
+        self.sub_duplex(data).map(SubSink::new)
     }
 
-    // TODO sub_stream, sub_sink, sub_reduce_stream, sub_reduce_sink
+    /// Start sending the given data as a duplex in which only the peer sends
+    /// items, see `SubStream`.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn sub_stream
+        (&mut self,
+         data: Data)
+         -> Result<SubStream<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex>, ExhaustedIds> {
+        self.sub_duplex(data).map(SubStream::new)
+    }
+
+    /// Start sending the given data as a duplex in which only this side sends
+    /// items, and the peer answers with a single reduced value, see
+    /// `SubReduceSink`.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn sub_reduce_sink(&mut self,
+                           data: Data)
+                           -> Result<SubReduceSink<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex>,
+                                     ExhaustedIds> {
+        self.sub_duplex(data).map(SubReduceSink::new)
+    }
+
+    /// Start sending the given data as a duplex in which only the peer sends
+    /// items, and this side answers with a single reduced value, see
+    /// `SubReduceStream`.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn sub_reduce_stream
+        (&mut self,
+         data: Data)
+         -> Result<SubReduceStream<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex>,
+                   ExhaustedIds> {
+        self.sub_duplex(data).map(SubReduceStream::new)
+    }
+
+    /// Start sending the given data as a duplex in which only this side sends
+    /// items, folding them into an accumulated value via `f`, starting from
+    /// `init`. The accumulated value is sent when the sink is closed, see
+    /// `SubFoldSink`.
+    ///
+    /// You have to call poll_complete to actually send the packet.
+    #[allow(clippy::type_complexity)]
+    pub fn sub_fold_sink<A, F>
+        (&mut self,
+         init: A,
+         f: F,
+         data: Data)
+         -> Result<SubFoldSink<'_, P, T, SinkErr, StreamErr, Data, R, OutSubDuplex, A, F>,
+                   ExhaustedIds>
+        where A: Into<Data>,
+              F: Fn(A, &Data) -> A
+    {
+        self.sub_duplex(data)
+            .map(|duplex| SubFoldSink::new(duplex, init, f))
+    }
 
     /// Creates a `Request` which allows correct handling of the packet. Use
     /// this for incoming packets for which
@@ -115,10 +954,19 @@ impl<P, T, SinkErr, StreamErr, Data, R> Dialogue<P, T, SinkErr, StreamErr, Data,
     ///
     /// In debug mode, this method checks `packet.get_type() == PacketType::Request`
     /// and panics if it does not return true.
-    pub fn packet_as_request(&mut self, packet: P) -> Request<P, T, SinkErr, StreamErr, Data, R> {
+    pub fn packet_as_request(&mut self,
+                             packet: P)
+                             -> Request<'_, P, T, SinkErr, StreamErr, Data, R> {
         debug_assert!(packet.get_type() == PacketType::Request);
 
-        unimplemented!()
+        let id = packet.get_id();
+        self.incoming
+            .insert(id, InRoute::Request { cancelled: false });
+        Request {
+            ps: self,
+            id,
+            packet,
+        }
     }
 
     /// Creates a `SubDuplex` which allows correct handling of the packet. Use
@@ -126,28 +974,249 @@ impl<P, T, SinkErr, StreamErr, Data, R> Dialogue<P, T, SinkErr, StreamErr, Data,
     /// packet.get_type() == PacketType::DuplexInitial` is true.
     ///
     /// Passing a packet which is not an incoming duplex packet will lead to
-    /// wrong communication.
+    /// wrong communication. This must be called before the `Stream`
+    /// implementation is polled again, otherwise the duplex has been discarded
+    /// and the returned `SubDuplex` only yields errors.
     ///
     /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
     /// and panics if it does not return true.
     pub fn packet_as_sub_duplex(&mut self,
                                 packet: P)
-                                -> SubDuplex<P, T, SinkErr, StreamErr, Data, InSubDuplex, R> {
+                                -> SubDuplex<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex> {
         debug_assert!(packet.get_type() == PacketType::DuplexInitial);
 
-        unimplemented!()
+        // The duplex has been registered when its initial packet arrived.
+        let id = packet.get_id();
+        if self.unaccepted == Some(id) {
+            self.unaccepted = None;
+        }
+        SubDuplex::new(self, id, false)
+    }
+
+    /// Creates a `SubStream` for an incoming duplex in which only the peer
+    /// sends items. Use this for incoming packets for which
+    /// packet.get_type() == PacketType::DuplexInitial` is true.
+    ///
+    /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
+    /// and panics if it does not return true.
+    pub fn packet_as_sub_stream(&mut self,
+                                packet: P)
+                                -> SubStream<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex> {
+        SubStream::new(self.packet_as_sub_duplex(packet))
+    }
+
+    /// Creates a `SubSink` for an incoming duplex in which only this side
+    /// sends items. Use this for incoming packets for which
+    /// packet.get_type() == PacketType::DuplexInitial` is true.
+    ///
+    /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
+    /// and panics if it does not return true.
+    pub fn packet_as_sub_sink(&mut self,
+                              packet: P)
+                              -> SubSink<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex> {
+        SubSink::new(self.packet_as_sub_duplex(packet))
+    }
+
+    /// Creates a `SubReduceStream` for an incoming duplex in which only the
+    /// peer sends items, and this side answers with a single reduced value.
+    /// Use this for incoming packets for which
+    /// packet.get_type() == PacketType::DuplexInitial` is true.
+    ///
+    /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
+    /// and panics if it does not return true.
+    pub fn packet_as_sub_reduce_stream
+        (&mut self,
+         packet: P)
+         -> SubReduceStream<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex> {
+        SubReduceStream::new(self.packet_as_sub_duplex(packet))
+    }
+
+    /// Creates a `SubReduceSink` for an incoming duplex in which only this
+    /// side sends items, and the peer answers with a single reduced value.
+    /// Use this for incoming packets for which
+    /// packet.get_type() == PacketType::DuplexInitial` is true.
+    ///
+    /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
+    /// and panics if it does not return true.
+    pub fn packet_as_sub_reduce_sink
+        (&mut self,
+         packet: P)
+         -> SubReduceSink<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex> {
+        SubReduceSink::new(self.packet_as_sub_duplex(packet))
+    }
+
+    /// Creates a `SubFoldStream` for an incoming duplex whose items the peer
+    /// folds into an accumulated value via a `SubFoldSink`. Use this for
+    /// incoming packets for which
+    /// packet.get_type() == PacketType::DuplexInitial` is true.
+    ///
+    /// In debug mode, this method checks `packet.get_type() == PacketType::DuplexInitial`
+    /// and panics if it does not return true.
+    pub fn packet_as_sub_fold_stream<A>
+        (&mut self,
+         packet: P)
+         -> SubFoldStream<'_, P, T, SinkErr, StreamErr, Data, R, InSubDuplex, A>
+        where A: From<Data>
+    {
+        SubFoldStream::new(self.packet_as_sub_duplex(packet))
+    }
+}
+
+// Used by the `Drop` implementations of the handles, which have no trait bounds.
+impl<P, T, SinkErr, StreamErr, Data, R> Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+    /// Removes the validator set by `with_packet_validation`.
+    pub(crate) fn remove_validator(&mut self) {
+        self.validator = None;
+    }
+
+    /// Returns whether new packets may be sent: Not after the dialogue closed,
+    /// and not after a client sent its close message.
+    fn may_send(&self) -> bool {
+        !self.closed && !self.sending_closed
     }
 
-    // // TODO variations of this for restricted duplexes: SubStream, SubSink, SubReduceStream, SubReduceSink
+    /// Queues a packet to be passed to the transport before any other packet.
+    ///
+    /// Queued packets are ordered by the `scheduling_priority` of their type,
+    /// but a packet never overtakes a packet with the same id, so the packets
+    /// of a request or duplex keep their order. Close and abort messages are
+    /// never reordered: They are sent after all packets queued before them,
+    /// and before all packets queued after them.
+    fn enqueue(&mut self, data: Option<Data>, id: PacketId, packet_type: PacketType) {
+        let priority = if data.is_none() && packet_type == PacketType::Message {
+            None
+        } else {
+            Some(packet_type.scheduling_priority())
+        };
+
+        let packet = (self.new_packet)(data, id, packet_type);
+        self.enqueue_packet(packet, id, priority);
+    }
+
+    /// Queues an already created packet, see `enqueue`. A priority of `None`
+    /// denotes a close or abort message.
+    fn enqueue_packet(&mut self, packet: P, id: PacketId, priority: Option<u8>) {
+        let mut index = self.queued.len();
+
+        if let Some(priority) = priority {
+            while index > 0 {
+                match self.queued[index - 1] {
+                    (Some(queued_priority), queued_id, _) if queued_priority > priority &&
+                                                             queued_id != id => index -= 1,
+                    _ => break,
+                }
+            }
+        }
+
+        self.queued.insert(index, (priority, id, packet));
+    }
+
+    /// Removes the route of a duplex, and frees its id if it was initiated by
+    /// this side.
+    fn release_duplex(&mut self, id: PacketId, out: bool) {
+        if out {
+            self.release_outgoing(id);
+        } else {
+            self.remove_incoming(id);
+        }
+    }
+
+    /// Returns whether a duplex still has a route.
+    fn has_duplex_route(&self, id: PacketId, out: bool) -> bool {
+        if out {
+            matches!(self.outgoing.get(&id), Some(&OutRoute::Duplex(_)))
+        } else {
+            matches!(self.incoming.get(&id), Some(&InRoute::Duplex(_)))
+        }
+    }
+
+    /// Returns the buffered packets of a duplex, or `None` if it has no route
+    /// (anymore).
+    fn duplex_route(&mut self, id: PacketId, out: bool) -> Option<&mut DuplexRoute<Data>> {
+        if out {
+            match self.outgoing.get_mut(&id) {
+                Some(&mut OutRoute::Duplex(ref mut duplex)) => Some(duplex),
+                _ => None,
+            }
+        } else {
+            match self.incoming.get_mut(&id) {
+                Some(&mut InRoute::Duplex(ref mut duplex)) => Some(duplex),
+                _ => None,
+            }
+        }
+    }
+
+    /// Removes the route of a request or duplex initiated by this side, and
+    /// frees its id.
+    fn release_outgoing(&mut self, id: PacketId) {
+        if self.outgoing.remove(&id).is_some() {
+            self.ids.free(id);
+            self.notify_closing();
+        }
+    }
+
+    /// Removes the route of a request or duplex initiated by the peer.
+    fn remove_incoming(&mut self, id: PacketId) {
+        if self.incoming.remove(&id).is_some() {
+            self.notify_closing();
+        }
+    }
+
+    /// Notifies a server waiting in `close` for its routes to be removed.
+    fn notify_closing(&mut self) {
+        if let Some(task) = self.closing_task.take() {
+            task.notify();
+        }
+    }
+}
+
+impl<P, T, SinkErr, StreamErr, Data> Dialogue<P, T, SinkErr, StreamErr, Data, Server>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>
+{
+    /// Creates a new `Dialogue` with the `Server` role over the given transport.
+    pub fn server(transport: T) -> Dialogue<P, T, SinkErr, StreamErr, Data, Server> {
+        Dialogue::new(transport)
+    }
+}
+
+impl<P, T, SinkErr, StreamErr, Data> Dialogue<P, T, SinkErr, StreamErr, Data, Client>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>
+{
+    /// Creates a new `Dialogue` with the `Client` role over the given transport.
+    pub fn client(transport: T) -> Dialogue<P, T, SinkErr, StreamErr, Data, Client> {
+        Dialogue::new(transport)
+    }
 }
 
 /// All incoming packets with fresh ids are emitted via this stream instance.
 ///
-/// To correctly use the packets, use the `packet_as_request` and `packet_as_duplex`
-/// methods of the `Dialogue`. TODO list other methods
+/// To correctly use the packets, use the `packet_as_request`,
+/// `packet_as_sub_duplex`, `packet_as_sub_stream` and `packet_as_sub_sink`
+/// methods of the `Dialogue`. Packets for requests and duplexes that already exist are
+/// routed to them instead, and packets for requests and duplexes that have
+/// been dropped are discarded.
+///
+/// A `DuplexInitial` packet must be passed to one of the `packet_as_*`
+/// methods before the stream is polled again. Otherwise the duplex is
+/// discarded, and this side's half of it is ended. Packets reusing the id of a
+/// request or duplex of the peer that is still live are discarded as well.
+///
+/// Dropping an emitted packet never leaks routing state. No state is kept for
+/// a request until it is passed to `packet_as_request`, a request that is
+/// dropped instead stays unanswered. A duplex is routed as soon as its
+/// `DuplexInitial` packet arrives, so that its items are buffered until it is
+/// accepted. If the packet is dropped instead, the route is removed once the
+/// stream is polled again.
 ///
 /// Even if you want to ignore all incoming requests, you must still consume
 /// this stream. Else, responses from the peer are not consumed either.
+///
+/// An error of the transport closes the dialogue, it is emitted once, after
+/// all packets received before it. Afterwards, and once the transport stream
+/// ended or the client closed the dialogue (for a server), the stream ends
+/// and keeps returning `None` without polling the transport again.
 impl<P, T, SinkErr, StreamErr, Data, R> Stream for Dialogue<P, T, SinkErr, StreamErr, Data, R>
     where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
           T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
@@ -157,8 +1226,170 @@ impl<P, T, SinkErr, StreamErr, Data, R> Stream for Dialogue<P, T, SinkErr, Strea
     type Error = TransportError<SinkErr, StreamErr>;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        unimplemented!()
+        if let Some(id) = self.unaccepted.take() {
+            self.discard_duplex(id);
+        }
+
+        if let Some(packet) = self.peeked.take() {
+            return Ok(Async::Ready(Some(self.emit(packet))));
+        }
+
+        loop {
+            if let Some(err) = self.invalid.pop_front() {
+                return Err(TransportError::ValidationError(err));
+            }
+
+            if let Some(packet) = self.fresh.pop_front() {
+                return Ok(Async::Ready(Some(self.emit(packet))));
+            }
+
+            if let Some(err) = self.error.take() {
+                return Err(err);
+            }
+
+            match self.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => {
+                    if self.fresh.is_empty() && self.invalid.is_empty() &&
+                       self.error.is_none() {
+                        return Ok(Async::Ready(None));
+                    }
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+/// Hands out the ids for packets sent by this side, and keeps track of the ids
+/// of requests and duplexes which are still in use.
+///
+/// Ids are taken from a counter which skips ids still in use once it wraps
+/// around. The counter advances by two, so that a server only uses even ids
+/// and a client only odd ones.
+struct PacketIdAllocator {
+    next: PacketId,
+    in_use: HashSet<PacketId>,
+    // The number of ids that can be in use at the same time.
+    limit: usize,
+}
+
+impl PacketIdAllocator {
+    fn new(first: PacketId) -> PacketIdAllocator {
+        PacketIdAllocator {
+            next: first,
+            in_use: HashSet::new(),
+            // Half of all ids have the parity of this side.
+            limit: (PacketId::MAX / 2) as usize + 1,
+        }
+    }
+
+    /// Reserves an id until it is passed to `free`.
+    fn alloc(&mut self) -> Result<PacketId, ExhaustedIds> {
+        if self.in_use.len() >= self.limit {
+            return Err(ExhaustedIds);
+        }
+
+        loop {
+            let id = self.next;
+            self.next = self.next.wrapping_add(2);
+
+            if self.in_use.insert(id) {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Frees a reserved id, so that it may be handed out again.
+    fn free(&mut self, id: PacketId) {
+        self.in_use.remove(&id);
     }
+
+    /// The number of reserved ids.
+    fn in_use(&self) -> usize {
+        self.in_use.len()
+    }
+}
+
+/// The routing state of a request or duplex initiated by this side.
+enum OutRoute<Data> {
+    /// A request, with the response once it has been received (`None` if the
+    /// peer declined the request).
+    Response(Option<Option<Data>>),
+    /// A duplex.
+    Duplex(DuplexRoute<Data>),
+}
+
+/// The routing state of a request or duplex initiated by the peer.
+enum InRoute<Data> {
+    /// A request, and whether the peer has cancelled it.
+    Request { cancelled: bool },
+    /// A duplex.
+    Duplex(DuplexRoute<Data>),
+}
+
+/// The packets received for a duplex that have not been consumed yet.
+struct DuplexRoute<Data> {
+    items: VecDeque<Data>,
+    // Set once the peer ended its half of the duplex, to the error data if it
+    // ended with an error.
+    end: Option<Option<Arc<Data>>>,
+}
+
+impl<Data> DuplexRoute<Data> {
+    fn new() -> DuplexRoute<Data> {
+        DuplexRoute {
+            items: VecDeque::new(),
+            end: None,
+        }
+    }
+
+    /// Receives a duplex packet from the peer, ignoring anything after the end
+    /// of the peer's half of the duplex.
+    fn receive<P: PacketReadable<Data = Data>>(&mut self, packet: P) {
+        if self.end.is_some() {
+            return;
+        }
+
+        match packet.get_type() {
+            PacketType::DuplexRequestEnd |
+            PacketType::DuplexResponseEnd => self.end = Some(packet.get_data().map(Arc::new)),
+            _ => {
+                if let Some(data) = packet.get_data() {
+                    self.items.push_back(data);
+                }
+            }
+        }
+    }
+}
+
+/// Creates a packet with the given data, id and type.
+fn new_packet<P: PacketWritable>(data: Option<P::Data>,
+                                 id: PacketId,
+                                 packet_type: PacketType)
+                                 -> P {
+    debug_assert!(data.is_some() || !packet_type.must_carry_data(),
+                  "{:?} packets must carry data",
+                  packet_type);
+
+    let mut packet = P::new(data);
+    packet.set_id(id);
+    packet.set_type(packet_type);
+    packet
+}
+
+// The ids of the messages without data which control the closing handshake:
+// A client's close message or a server's request to close, the server's
+// answer to the client's close message, and an abort.
+const CLOSE_ID: PacketId = 0;
+const CLOSE_ANSWER_ID: PacketId = 1;
+const ABORT_ID: PacketId = 2;
+
+/// Ignores all notifications, used to poll without a task.
+struct NoopNotify;
+
+impl Notify for NoopNotify {
+    fn notify(&self, _id: usize) {}
 }
 
 /// An error indicating that an operation failed because the corresponding
@@ -178,11 +1409,78 @@ impl Error for ClosedDialogue {
     }
 }
 
+/// An error indicating that a message, request or duplex could not be sent,
+/// because all ids available to this side of the `Dialogue` are in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExhaustedIds;
+
+impl fmt::Display for ExhaustedIds {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "ExhaustedIds")
+    }
+}
+
+impl Error for ExhaustedIds {}
+
+/// The error of `Dialogue::message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+    /// The `Dialogue` has been closed.
+    ClosedDialogue,
+    /// All ids available to this side of the `Dialogue` are in use.
+    ExhaustedIds,
+}
+
+impl From<ClosedDialogue> for MessageError {
+    fn from(_: ClosedDialogue) -> MessageError {
+        MessageError::ClosedDialogue
+    }
+}
+
+impl From<ExhaustedIds> for MessageError {
+    fn from(_: ExhaustedIds) -> MessageError {
+        MessageError::ExhaustedIds
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MessageError::ClosedDialogue => write!(fmt, "ClosedDialogue"),
+            MessageError::ExhaustedIds => write!(fmt, "ExhaustedIds"),
+        }
+    }
+}
+
+impl Error for MessageError {}
+
+/// The error of responding to a `Request`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RespondError {
+    /// The `Dialogue` has been closed.
+    ClosedDialogue,
+    /// The peer cancelled the request before the response was queued.
+    AlreadyCancelled,
+}
+
+impl fmt::Display for RespondError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RespondError::ClosedDialogue => write!(fmt, "ClosedDialogue"),
+            RespondError::AlreadyCancelled => write!(fmt, "AlreadyCancelled"),
+        }
+    }
+}
+
+impl Error for RespondError {}
+
 /// A request that has been received from the peer.
 ///
 /// This implements `Future` to be notified when/if the peer cancels the request.
 pub struct Request<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> {
     ps: &'ps mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    id: PacketId,
+    packet: P,
 }
 
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Request<'ps,
@@ -198,38 +1496,72 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Reque
 {
     /// Gets the data that was sent with the request.
     pub fn get_data(&self) -> Data {
-        unimplemented!()
+        self.packet
+            .get_data()
+            .expect("requests emitted by a Dialogue carry data")
+    }
+
+    /// Gets the `PacketType` of the packet this `Request` was created from,
+    /// which is always `PacketType::Request`.
+    pub fn get_type(&self) -> PacketType {
+        PacketType::Request
     }
 
     /// Consumes the `Request` and writes some response data to the peer.
     ///
-    /// The `StartSend` error variant is returned if the packet stream has closed.
+    /// The `StartSend` error variant is returned if the packet stream has closed,
+    /// or if the `Dialogue` already processed the peer's cancellation of the
+    /// request. The response is queued in the `Dialogue` otherwise, so this
+    /// never returns `AsyncSink::NotReady`.
+    ///
+    /// A cancellation which arrives after the response has been queued is
+    /// ignored, it is up to the peer to ignore the response it no longer
+    /// wants.
     ///
     /// To make sure the response has actually been sent, call `poll_complete`
-    /// on either the `Request` or the `Dialogue`.
-    pub fn start_responding(self, data: Data) -> StartSend<Self, ClosedDialogue> {
-        unimplemented!()
+    /// on the `Dialogue`.
+    pub fn start_responding(self, data: Data) -> StartSend<Self, RespondError> {
+        self.respond(Some(data))
     }
 
-    /// Consumes the `Request` and cancels it.
+    /// Consumes the `Request` and cancels it, by sending a response without
+    /// data.
     ///
-    /// The `StartSend` error variant is returned if the packet stream has closed.
+    /// The `StartSend` error variant is returned if the packet stream has closed,
+    /// or if the `Dialogue` already processed the peer's cancellation of the
+    /// request. The cancellation is queued in the `Dialogue` otherwise, so this
+    /// never returns `AsyncSink::NotReady`.
     ///
     /// To make sure the cancellation has actually been sent, call `poll_complete`
-    /// on either the `Request` or the `Dialogue`.
-    pub fn start_cancelling(self) -> StartSend<Self, ClosedDialogue> {
-        unimplemented!()
+    /// on the `Dialogue`.
+    pub fn start_cancelling(self) -> StartSend<Self, RespondError> {
+        self.respond(None)
+    }
+
+    fn respond(self, data: Option<Data>) -> StartSend<Self, RespondError> {
+        if let Some(&InRoute::Request { cancelled: true }) = self.ps.incoming.get(&self.id) {
+            return Err(RespondError::AlreadyCancelled);
+        }
+
+        if !self.ps.may_send() {
+            return Err(RespondError::ClosedDialogue);
+        }
+
+        self.ps.enqueue(data, self.id, PacketType::Response);
+        Ok(AsyncSink::Ready)
     }
 
     /// Delegates to the `poll_complete` method of the `Dialogue`.
     pub fn poll_complete(&mut self) -> Poll<(), ClosedDialogue> {
-        unimplemented!()
+        self.ps.poll_complete().map_err(|_| ClosedDialogue)
     }
 }
 
-/// The future completes when this request is cancelled. It may never complete.
-/// It is guaranteed to never yield an error (and the error type will be changed
-/// once `!` becomes a legal rust type).
+/// The future completes when this request is cancelled, or when no more
+/// packets are read because the peer sent its final close message or the
+/// `Dialogue` has closed, so that no cancellation can arrive anymore. It may
+/// never complete. It is guaranteed to never yield an error (and the error type will
+/// be changed once `!` becomes a legal rust type).
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Future
     for
     Request<'ps, P, T, SinkErr, StreamErr, Data, R>
@@ -241,7 +1573,17 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Futur
     type Error = ();
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        unimplemented!();
+        loop {
+            if let Some(&InRoute::Request { cancelled: true }) = self.ps.incoming.get(&self.id) {
+                return Ok(Async::Ready(()));
+            }
+
+            match self.ps.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => return Ok(Async::Ready(())),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
     }
 }
 
@@ -250,7 +1592,7 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Futur
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Drop
     for Request<'ps, P, T, SinkErr, StreamErr, Data, R> {
     fn drop(&mut self) {
-        unimplemented!()
+        self.ps.remove_incoming(self.id);
     }
 }
 
@@ -284,6 +1626,10 @@ impl SubDuplexType for InSubDuplex {
 ///
 /// The `SubDuplexType` parameter does not exist at runtime, it just enforces some
 /// static type safety about how duplex cancellation works.
+///
+/// Items of a duplex initiated by this side are sent as `DuplexRequest`
+/// packets and its end as a `DuplexRequestEnd` packet, for a duplex initiated
+/// by the peer these are `DuplexResponse` and `DuplexResponseEnd` packets.
 pub struct SubDuplex<'ps,
                      P: 'ps,
                      T: 'ps,
@@ -294,7 +1640,64 @@ pub struct SubDuplex<'ps,
                      SubDuplexType: 'static>
 {
     ps: &'ps mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
-    duplex_type: PhantomData<SubDuplexType>,
+    id: PacketId,
+    // Whether this side initiated the duplex.
+    out: bool,
+    state: DuplexState,
+    duplex_type: PhantomData<fn() -> SubDuplexType>,
+}
+
+// Used by the `Drop` implementation, which has no trait bounds.
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+    fn item_type(&self) -> PacketType {
+        if self.out {
+            PacketType::DuplexRequest
+        } else {
+            PacketType::DuplexResponse
+        }
+    }
+
+    fn end_type(&self) -> PacketType {
+        if self.out {
+            PacketType::DuplexRequestEnd
+        } else {
+            PacketType::DuplexResponseEnd
+        }
+    }
+
+    /// Queues the end packet of this side, unless it has been sent already.
+    fn end(&mut self, err: Option<Data>) -> Result<(), ClosedDialogue> {
+        if self.state.is_local_closed() {
+            return Ok(());
+        }
+
+        if !self.ps.may_send() || self.ps.duplex_route(self.id, self.out).is_none() {
+            return Err(ClosedDialogue);
+        }
+
+        let end_type = self.end_type();
+        self.ps.enqueue(err, self.id, end_type);
+        self.state = self.state.close_local();
+        Ok(())
+    }
+
+    /// Queues the end packet of this side, carrying the given error data.
+    pub(crate) fn end_with_error(&mut self, err: Data) -> Result<(), ClosedDialogue> {
+        self.end(Some(err))
+    }
+
+    /// Queues a last item, followed by the end packet of this side.
+    pub(crate) fn end_with_item(&mut self, item: Data) -> Result<(), ClosedDialogue> {
+        if self.state.is_local_closed() || !self.ps.may_send() ||
+           self.ps.duplex_route(self.id, self.out).is_none() {
+            return Err(ClosedDialogue);
+        }
+
+        let item_type = self.item_type();
+        self.ps.enqueue(Some(item), self.id, item_type);
+        self.end(None)
+    }
 }
 
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
@@ -303,20 +1706,190 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDu
           T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
           R: Role
 {
+    fn new(ps: &'ps mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+           id: PacketId,
+           out: bool)
+           -> SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        SubDuplex {
+            ps,
+            id,
+            out,
+            state: DuplexState::Open,
+            duplex_type: PhantomData,
+        }
+    }
+
     /// Same as `close`, but the receiving duplex is given some error data.
     pub fn close_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
-        unimplemented!()
+        self.close_with(Some(err))
+    }
+
+    /// Sends the end packet, flushes and waits for the end of the peer's half.
+    fn close_with(&mut self, err: Option<Data>) -> Poll<(), ClosedDialogue> {
+        self.end(err)?;
+
+        loop {
+            if self.ps.poll_complete().map_err(|_| ClosedDialogue)?.is_not_ready() {
+                return Ok(Async::NotReady);
+            }
+
+            match self.ps.duplex_route(self.id, self.out) {
+                Some(route) => {
+                    if route.end.is_some() {
+                        self.state = self.state.close_remote();
+                    }
+                }
+                None => return Err(ClosedDialogue),
+            }
+
+            if self.state == DuplexState::FullyClosed {
+                return Ok(Async::Ready(()));
+            }
+
+            match self.ps.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => return Err(ClosedDialogue),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
     }
 
     /// Directly close the stream (without error), not waiting for confirmation
     /// by the peer and dropping any outstanding responses or stream packets.
     pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
-        unimplemented!()
+        self.abort_with(None)
     }
 
     /// Same as `abort`, but the receiving duplex is given some error data.
     pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
-        unimplemented!()
+        self.abort_with(Some(err))
+    }
+
+    /// Sends the end packet, releases the duplex and flushes.
+    fn abort_with(&mut self, err: Option<Data>) -> Poll<(), ClosedDialogue> {
+        if self.state != DuplexState::Aborted {
+            self.end(err)?;
+            self.ps.release_duplex(self.id, self.out);
+            self.state = DuplexState::Aborted;
+        }
+
+        self.ps.poll_complete().map_err(|_| ClosedDialogue)
+    }
+
+    /// Gets the `PacketType` of the packet which initiated the duplex, which
+    /// is always `PacketType::DuplexInitial`.
+    pub fn get_type(&self) -> PacketType {
+        PacketType::DuplexInitial
+    }
+
+    /// Returns whether exactly one side ended its half of the duplex.
+    ///
+    /// The peer's half only counts as ended once its end has been emitted by
+    /// the `Stream` implementation, or a `close` noticed it.
+    pub fn is_half_closed(&self) -> bool {
+        !self.is_ended() &&
+        matches!(self.state, DuplexState::LocalClosed | DuplexState::RemoteClosed)
+    }
+
+    /// Returns whether the duplex is done: Both sides ended their halves, this
+    /// side aborted it, or the `Dialogue` has been closed.
+    pub fn is_ended(&self) -> bool {
+        matches!(self.state, DuplexState::FullyClosed | DuplexState::Aborted) ||
+        !self.ps.has_duplex_route(self.id, self.out)
+    }
+
+    /// Converts this into a `ChunkedSubDuplex`, which emits the received items
+    /// in batches of up to `size` items.
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunks(self,
+                  size: usize)
+                  -> ChunkedSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        ChunkedSubDuplex::new(self, size)
+    }
+
+    /// Converts this into an `OnCloseSubDuplex`, which calls `f` once the peer
+    /// ends the duplex: with `None` if it ended regularly, or with the error
+    /// data if it ended with an error. The error data is an `Arc`, since it is
+    /// shared with the `SubStreamError::EndWithError` that is emitted
+    /// afterwards.
+    pub fn on_close<F>(self,
+                       f: F)
+                       -> OnCloseSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, F>
+        where F: FnOnce(Option<Arc<Data>>)
+    {
+        OnCloseSubDuplex::new(self, f)
+    }
+
+    /// Returns a future which receives exactly `n` items from this duplex.
+    ///
+    /// The future errors with `SubStreamError::TooFewItems` if the duplex ends
+    /// before `n` items were received. Once `n` items have been received, the
+    /// `SubDuplex` is dropped, so any further items are ignored, unless the
+    /// future is made `strict`.
+    pub fn expect_exactly(self,
+                          n: usize)
+                          -> ExpectExactly<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        ExpectExactly::new(self, n)
+    }
+
+    /// Returns an `io::Write` adapter which sends the written bytes as items
+    /// on this duplex whenever it is flushed.
+    pub fn as_write(&mut self)
+                    -> SubDuplexWriter<'_, 'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+        where Data: From<Vec<u8>>
+    {
+        SubDuplexWriter::new(self)
+    }
+}
+
+/// Which halves of a `SubDuplex` have been ended.
+///
+/// Both sides may end their half at the same time, each side then receives
+/// the end packet of the peer after having sent its own one, which moves the
+/// duplex from `LocalClosed` to `FullyClosed` as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DuplexState {
+    /// Neither side ended its half.
+    Open,
+    /// This side sent its end packet, the peer did not end its half yet.
+    LocalClosed,
+    /// The peer ended its half, this side did not send its end packet yet.
+    RemoteClosed,
+    /// Both sides ended their halves.
+    FullyClosed,
+    /// This side aborted the duplex, its route is gone.
+    Aborted,
+}
+
+impl DuplexState {
+    /// Whether this side sent its end packet.
+    fn is_local_closed(self) -> bool {
+        match self {
+            DuplexState::Open |
+            DuplexState::RemoteClosed => false,
+            DuplexState::LocalClosed |
+            DuplexState::FullyClosed |
+            DuplexState::Aborted => true,
+        }
+    }
+
+    /// The state after this side sent its end packet.
+    fn close_local(self) -> DuplexState {
+        match self {
+            DuplexState::Open => DuplexState::LocalClosed,
+            DuplexState::RemoteClosed => DuplexState::FullyClosed,
+            state => state,
+        }
+    }
+
+    /// The state after receiving the end packet of the peer.
+    fn close_remote(self) -> DuplexState {
+        match self {
+            DuplexState::Open => DuplexState::RemoteClosed,
+            DuplexState::LocalClosed => DuplexState::FullyClosed,
+            state => state,
+        }
     }
 }
 
@@ -336,28 +1909,65 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDu
     type SinkError = ClosedDialogue;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        unimplemented!()
+        if self.state.is_local_closed() || self.ps.duplex_route(self.id, self.out).is_none() {
+            return Err(ClosedDialogue);
+        }
+
+        let packet_type = self.item_type();
+        self.ps.start_send_data(item, self.id, packet_type)
     }
 
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
-        unimplemented!()
+        self.ps.poll_complete().map_err(|_| ClosedDialogue)
     }
 
     /// Performs a half-close of the duplex. Will wait for completely closing
     /// the duplex until the peer confirms the close. In between, responses and
     /// stream packets are still received.
     fn close(&mut self) -> Poll<(), Self::SinkError> {
-        unimplemented!()
+        self.close_with(None)
     }
 }
 
 /// The error for `Stream` implementation of substreams.
-#[derive(Debug)]
+///
+/// Error data is shared via an `Arc`, so that it can be handed to multiple
+/// consumers without requiring `Data: Clone`.
+#[derive(Debug, PartialEq, Eq)]
 pub enum SubStreamError<Data> {
     /// The corresponding dialogue has been closed.
     ClosedDialogue,
     /// The peer terminated the stream with some error data.
-    EndWithError(Data),
+    EndWithError(Arc<Data>),
+    /// The stream ended before an expected number of items was received.
+    TooFewItems {
+        /// The number of items received before the stream ended.
+        received: usize,
+        /// The number of items that should have been received.
+        expected: usize,
+    },
+    /// The stream yielded more than an expected number of items.
+    TooManyItems {
+        /// The number of items that should have been received.
+        expected: usize,
+    },
+}
+
+/// Cloning only clones the `Arc` around the error data, so this does not
+/// require `Data: Clone`.
+impl<Data> Clone for SubStreamError<Data> {
+    fn clone(&self) -> SubStreamError<Data> {
+        match *self {
+            SubStreamError::ClosedDialogue => SubStreamError::ClosedDialogue,
+            SubStreamError::EndWithError(ref data) => SubStreamError::EndWithError(data.clone()),
+            SubStreamError::TooFewItems { received, expected } => {
+                SubStreamError::TooFewItems { received, expected }
+            }
+            SubStreamError::TooManyItems { expected } => {
+                SubStreamError::TooManyItems { expected }
+            }
+        }
+    }
 }
 
 impl<Data: fmt::Display> fmt::Display for SubStreamError<Data> {
@@ -365,15 +1975,23 @@ impl<Data: fmt::Display> fmt::Display for SubStreamError<Data> {
         match *self {
             SubStreamError::ClosedDialogue => write!(fmt, "ClosedDialogue"),
             SubStreamError::EndWithError(ref data) => write!(fmt, "EndWithError: {}", data),
+            SubStreamError::TooFewItems { received, expected } => {
+                write!(fmt, "TooFewItems: received {} of {} items", received, expected)
+            }
+            SubStreamError::TooManyItems { expected } => {
+                write!(fmt, "TooManyItems: received more than {} items", expected)
+            }
         }
     }
 }
 
-impl<Data: Error> Error for SubStreamError<Data> {
-    fn description(&self) -> &str {
+impl<Data: Error + 'static> Error for SubStreamError<Data> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
-            SubStreamError::ClosedDialogue => "dialogue has been closed",
-            SubStreamError::EndWithError(ref data) => data.description(),
+            SubStreamError::ClosedDialogue |
+            SubStreamError::TooFewItems { .. } |
+            SubStreamError::TooManyItems { .. } => None,
+            SubStreamError::EndWithError(ref data) => Some(&**data),
         }
     }
 }
@@ -398,12 +2016,45 @@ impl<'ps,
     type Error = SubStreamError<Data>;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        unimplemented!()
+        if self.state == DuplexState::Aborted {
+            return Ok(Async::Ready(None));
+        }
+
+        loop {
+            match self.ps.duplex_route(self.id, self.out) {
+                Some(route) => {
+                    if let Some(item) = route.items.pop_front() {
+                        return Ok(Async::Ready(Some(item)));
+                    }
+
+                    if route.end.is_some() {
+                        self.state = self.state.close_remote();
+                    }
+
+                    match route.end {
+                        Some(None) => return Ok(Async::Ready(None)),
+                        Some(Some(ref err)) => {
+                            return Err(SubStreamError::EndWithError(err.clone()))
+                        }
+                        None => {}
+                    }
+                }
+                None => return Err(SubStreamError::ClosedDialogue),
+            }
+
+            match self.ps.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => return Err(SubStreamError::ClosedDialogue),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
     }
 }
 
 /// When dropping a `SubDuplex`, the corresponding `Dialogue` is notified so
-/// that it stops waiting for more duplex packets.
+/// that it stops waiting for more duplex packets. If this side has not ended
+/// the duplex yet, the end packet is queued, to be sent with the next call to
+/// `poll_complete` on the `Dialogue`.
 impl<'ps,
      P: 'ps,
      T: 'ps,
@@ -415,7 +2066,10 @@ impl<'ps,
     for
     SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
     fn drop(&mut self) {
-        unimplemented!()
+        if self.state != DuplexState::Aborted {
+            let _ = self.end(None);
+            self.ps.release_duplex(self.id, self.out);
+        }
     }
 }
 
@@ -423,6 +2077,9 @@ impl<'ps,
 /// cancel the original request.
 pub struct Response<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> {
     ps: &'ps mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    id: PacketId,
+    // Set once the future resolved.
+    resolved: bool,
 }
 
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Response<'ps,
@@ -436,21 +2093,75 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Respo
           T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
           R: Role
 {
-    /// Cancel the original request. To make sure the response has actually been
-    /// sent, call `poll_complete` on either the `Response` or the `Dialogue`.
+    /// Consumes the `Response` and cancels the original request, by sending a
+    /// request without data. A response arriving afterwards is ignored.
     ///
-    /// Once the original request has been cancelled, this `Response` should be
-    /// dropped.
-    pub fn start_cancel() -> StartSend<Self, ClosedDialogue> {
-        unimplemented!()
+    /// The `StartSend` error variant is returned if the packet stream has closed.
+    /// The cancellation is queued in the `Dialogue` otherwise, so this never
+    /// returns `AsyncSink::NotReady`.
+    ///
+    /// To make sure the cancellation has actually been sent, call
+    /// `poll_complete` on the `Dialogue`.
+    pub fn start_cancel(mut self) -> StartSend<Self, ClosedDialogue> {
+        self.cancel()?;
+        Ok(AsyncSink::Ready)
     }
 
-    /// Delegates to the `poll_complete` method of the `Dialogue`.
+    /// Queues the cancellation of the request, see `start_cancel`.
+    pub(crate) fn cancel(&mut self) -> Result<(), ClosedDialogue> {
+        if !self.ps.may_send() {
+            return Err(ClosedDialogue);
+        }
+
+        self.ps.enqueue(None, self.id, PacketType::Request);
+        Ok(())
+    }
+
+    /// Returns the id of the request.
+    pub(crate) fn id(&self) -> PacketId {
+        self.id
+    }
+
+    /// Converts this into a `TimedResponse`, which cancels the request and
+    /// errors if the given delay future resolves before the response arrives.
     ///
-    /// Once the original request has been cancelled, this `Response` should be
-    /// dropped.
+    /// The delay is typically a timer resolving at the deadline.
+    pub fn with_timeout<D>(self,
+                           delay: D)
+                           -> TimedResponse<'ps, P, T, SinkErr, StreamErr, Data, R, D>
+        where D: Future<Item = ()>
+    {
+        TimedResponse::new(self, Some(delay))
+    }
+
+    /// Converts this into a `TimedResponse` which gives up at the given
+    /// `deadline`, using a delay created by the `timer`.
+    pub fn with_deadline<Ti>(self,
+                             timer: &Ti,
+                             deadline: Instant)
+                             -> TimedResponse<'ps, P, T, SinkErr, StreamErr, Data, R, Ti::Delay>
+        where Ti: Timer
+    {
+        self.with_timeout(timer.delay_until(deadline))
+    }
+
+    /// Gets the `PacketType` of the packet this `Response` resolves to, which
+    /// is always `PacketType::Response`.
+    pub fn get_type(&self) -> PacketType {
+        PacketType::Response
+    }
+
+    /// Returns whether the response (or the peer's refusal to answer) has been
+    /// received: Either the future resolved already, or polling it resolves
+    /// right away.
+    pub fn is_resolved(&self) -> bool {
+        self.resolved ||
+        matches!(self.ps.outgoing.get(&self.id), Some(&OutRoute::Response(Some(_))))
+    }
+
+    /// Delegates to the `poll_complete` method of the `Dialogue`.
     pub fn poll_complete(&mut self) -> Poll<(), ClosedDialogue> {
-        unimplemented!()
+        self.ps.poll_complete().map_err(|_| ClosedDialogue)
     }
 }
 
@@ -460,8 +2171,8 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Respo
 ///
 /// It errors if the underlying `Dialogue` has shut down.
 ///
-/// If the original request has been cancelled by this side of the dialogue,
-/// this future may never resolve and should be `drop`ped.
+/// A response which arrives before the future is polled is buffered by the
+/// `Dialogue`.
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Future
     for
     Response<'ps, P, T, SinkErr, StreamErr, Data, R>
@@ -473,7 +2184,24 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Futur
     type Error = ClosedDialogue;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        unimplemented!();
+        loop {
+            let response = match self.ps.outgoing.get_mut(&self.id) {
+                Some(&mut OutRoute::Response(ref mut response)) => response.take(),
+                _ => return Err(ClosedDialogue),
+            };
+
+            if let Some(data) = response {
+                self.ps.release_outgoing(self.id);
+                self.resolved = true;
+                return Ok(Async::Ready(data));
+            }
+
+            match self.ps.poll_read() {
+                Async::Ready(true) => {}
+                Async::Ready(false) => return Err(ClosedDialogue),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
     }
 }
 
@@ -482,6 +2210,196 @@ impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Futur
 impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps> Drop
     for Response<'ps, P, T, SinkErr, StreamErr, Data, R> {
     fn drop(&mut self) {
-        unimplemented!()
+        self.ps.release_outgoing(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::*;
+    use testing::{paired_transports, ConcretePacket, TestDialogue};
+
+    #[test]
+    fn exhausted_ids_are_reported() {
+        let (a, _b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+        client.ids.limit = 2;
+
+        mem::forget(client.request(vec![0]).unwrap());
+        mem::forget(client.sub_duplex(vec![1]).unwrap());
+        assert_eq!(client.active_ids(), 2);
+
+        assert_eq!(client.message(vec![2]).unwrap_err(), MessageError::ExhaustedIds);
+        assert!(client.request(vec![3]).is_err());
+        assert!(client.sub_duplex(vec![4]).is_err());
+        assert!(client.sub_sink(vec![5]).is_err());
+        assert!(client.sub_stream(vec![6]).is_err());
+        assert_eq!(client.active_ids(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "must carry data")]
+    #[cfg(debug_assertions)]
+    fn packets_which_must_carry_data_are_not_created_empty() {
+        let _: ConcretePacket = new_packet(None, 1, PacketType::DuplexRequest);
+    }
+
+    #[test]
+    fn queued_packets_are_ordered_by_priority() {
+        let (a, _b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+
+        client.enqueue(Some(vec![0]), 1, PacketType::Message);
+        client.enqueue(Some(vec![1]), 3, PacketType::DuplexInitial);
+        client.enqueue(Some(vec![2]), 3, PacketType::DuplexRequest);
+        client.enqueue(Some(vec![3]), 5, PacketType::DuplexRequest);
+        client.enqueue(Some(vec![4]), 7, PacketType::Request);
+        // The end may overtake other duplexes, but not its own items.
+        client.enqueue(None, 3, PacketType::DuplexRequestEnd);
+        client.enqueue(None, CLOSE_ID, PacketType::Message);
+        // Nothing overtakes the close message.
+        client.enqueue(None, 2, PacketType::Response);
+
+        let queued: Vec<(PacketId, PacketType)> = client
+            .queued
+            .iter()
+            .map(|&(_, id, ref packet)| (id, packet.get_type()))
+            .collect();
+        assert_eq!(queued,
+                   vec![(7, PacketType::Request),
+                        (3, PacketType::DuplexInitial),
+                        (3, PacketType::DuplexRequest),
+                        (3, PacketType::DuplexRequestEnd),
+                        (5, PacketType::DuplexRequest),
+                        (1, PacketType::Message),
+                        (CLOSE_ID, PacketType::Message),
+                        (2, PacketType::Response)]);
+    }
+
+    fn poll_stream<S: Stream>(stream: &mut S) -> Poll<Option<S::Item>, S::Error> {
+        let notify = NotifyHandle::from(Arc::new(NoopNotify));
+        executor::spawn(stream).poll_stream_notify(&notify, 0)
+    }
+
+    #[test]
+    fn dropped_packets_leave_no_routes() {
+        let (mut raw, b) = paired_transports();
+        let mut server: TestDialogue<_> = Dialogue::server(b);
+
+        for i in 0..1000 {
+            let packet_type = if i % 2 == 0 {
+                PacketType::Request
+            } else {
+                PacketType::DuplexInitial
+            };
+            raw.start_send(new_packet(Some(vec![0]), 2 * i + 1, packet_type)).unwrap();
+        }
+
+        let mut emitted = 0;
+        while let Async::Ready(Some(_)) = poll_stream(&mut server).unwrap() {
+            emitted += 1;
+        }
+        assert_eq!(emitted, 1000);
+        assert!(server.incoming.is_empty());
+
+        // Each dropped duplex has been ended by the server.
+        assert!(server.try_poll_complete().unwrap());
+        let mut ended = 0;
+        while let Async::Ready(Some(packet)) = poll_stream(&mut raw).unwrap() {
+            assert_eq!(packet.get_type(), PacketType::DuplexResponseEnd);
+            ended += 1;
+        }
+        assert_eq!(ended, 500);
+    }
+
+    #[test]
+    fn dropped_and_rejected_handles_leave_no_routes() {
+        let (a, _b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+        drop(client.request(vec![0]).unwrap());
+        drop(client.sub_duplex(vec![1]).unwrap());
+        assert_eq!(client.active_ids(), 0);
+
+        let (mut raw, b) = paired_transports();
+        let server: TestDialogue<_> = Dialogue::server(b);
+        let mut server = server.recv_filter(|packet: &ConcretePacket| packet.get_id() > 4);
+
+        // The first two are rejected by the filter, the others are accepted and
+        // then dropped.
+        raw.start_send(new_packet(Some(vec![0]), 1, PacketType::Request)).unwrap();
+        raw.start_send(new_packet(Some(vec![1]), 3, PacketType::DuplexInitial)).unwrap();
+        raw.start_send(new_packet(Some(vec![2]), 5, PacketType::Request)).unwrap();
+        raw.start_send(new_packet(Some(vec![3]), 7, PacketType::DuplexInitial)).unwrap();
+
+        let mut accepted = 0;
+        while let Async::Ready(Some(packet)) = poll_stream(&mut server).unwrap() {
+            match packet.get_type() {
+                PacketType::Request => drop(server.get_mut().packet_as_request(packet)),
+                _ => drop(server.get_mut().packet_as_sub_duplex(packet)),
+            }
+            accepted += 1;
+        }
+        assert_eq!(accepted, 2);
+        assert!(server.get_ref().incoming.is_empty());
+    }
+
+    #[test]
+    fn needs_flush_reports_queued_packets() {
+        let (a, _b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+        assert!(!client.needs_flush());
+
+        client.enqueue(Some(vec![0]), 1, PacketType::Message);
+        assert!(client.needs_flush());
+        client.enqueue(Some(vec![1]), 3, PacketType::Message);
+        assert!(client.needs_flush());
+
+        assert!(client.try_poll_complete().unwrap());
+        assert!(!client.needs_flush());
+    }
+
+    #[test]
+    fn id_counter_and_free_ids_are_reported() {
+        let (a, _b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+        client.ids.limit = 3;
+
+        assert_eq!(client.request_id_counter(), 1);
+        assert_eq!(client.free_id_count(), 3);
+
+        mem::forget(client.request(vec![0]).unwrap());
+        mem::forget(client.sub_duplex(vec![1]).unwrap());
+        assert_eq!(client.request_id_counter(), 5);
+        assert_eq!(client.free_id_count(), 1);
+
+        drop(client.request(vec![2]).unwrap());
+        assert_eq!(client.request_id_counter(), 7);
+        assert_eq!(client.free_id_count(), 1);
+    }
+
+    #[test]
+    fn wraparound_skips_ids_in_use() {
+        let (a, b) = paired_transports();
+        let mut client: TestDialogue<_> = Dialogue::client(a);
+        let mut server: TestDialogue<_> = Dialogue::server(b);
+
+        // A long-lived duplex keeps the first id of the client.
+        mem::forget(client.sub_duplex(vec![0]).unwrap());
+        client.ids.next = PacketId::MAX;
+
+        client.message(vec![1]).unwrap();
+        client.message(vec![2]).unwrap();
+        client.poll_complete().unwrap();
+
+        let ids: Vec<PacketId> = server
+            .by_ref()
+            .take(3)
+            .map(|packet| packet.get_id())
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(ids, vec![1, PacketId::MAX, 3]);
     }
 }
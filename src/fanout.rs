@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use futures::{Future, Sink, Stream, Poll, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{Response, Role, ExhaustedIds};
+
+/// A future which sends the same request to several peers and collects all
+/// responses, created via `Dialogue::fanout_request`.
+///
+/// Resolves to the responses in the order of the peers. By default, a peer
+/// whose `Response` errors (because its `Dialogue` has been closed), or to
+/// which no request could be sent because all its ids are in use, is treated
+/// like a peer that declined to answer, i.e. its entry is `None`. After
+/// calling `propagate_errors`, the future errors with a `FanoutError` instead.
+pub struct FanoutFuture<'a, P: 'a, T: 'a, SinkErr: 'a, StreamErr: 'a, Data: 'a, R: 'a> {
+    entries: Vec<Entry<'a, P, T, SinkErr, StreamErr, Data, R>>,
+    propagate_errors: bool,
+}
+
+enum Entry<'a, P: 'a, T: 'a, SinkErr: 'a, StreamErr: 'a, Data: 'a, R: 'a> {
+    Pending(Response<'a, P, T, SinkErr, StreamErr, Data, R>),
+    Done(Option<Data>),
+    Failed(FanoutError),
+}
+
+impl<'a, P: 'a, T: 'a, SinkErr: 'a, StreamErr: 'a, Data: 'a, R: 'a> FanoutFuture<'a,
+                                                                                 P,
+                                                                                 T,
+                                                                                 SinkErr,
+                                                                                 StreamErr,
+                                                                                 Data,
+                                                                                 R> {
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn new(responses: Vec<Result<Response<'a, P, T, SinkErr, StreamErr, Data, R>,
+                                            ExhaustedIds>>)
+                      -> FanoutFuture<'a, P, T, SinkErr, StreamErr, Data, R> {
+        FanoutFuture {
+            entries: responses
+                .into_iter()
+                .enumerate()
+                .map(|(index, response)| match response {
+                         Ok(response) => Entry::Pending(response),
+                         Err(ExhaustedIds) => Entry::Failed(FanoutError::ExhaustedIds { index }),
+                     })
+                .collect(),
+            propagate_errors: false,
+        }
+    }
+
+    /// Makes the future error with the first `FanoutError` it encounters,
+    /// instead of treating the failing peer like a peer that declined to
+    /// answer.
+    pub fn propagate_errors(mut self) -> FanoutFuture<'a, P, T, SinkErr, StreamErr, Data, R> {
+        self.propagate_errors = true;
+        self
+    }
+}
+
+impl<'a, P: 'a, T: 'a, SinkErr: 'a, StreamErr: 'a, Data: 'a, R: 'a> Future
+    for
+    FanoutFuture<'a, P, T, SinkErr, StreamErr, Data, R>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Vec<Option<Data>>;
+    type Error = FanoutError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut done = true;
+
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            let result = match *entry {
+                Entry::Pending(ref mut response) => {
+                    match response.poll_complete().and_then(|_| response.poll()) {
+                        Ok(Async::Ready(data)) => Entry::Done(data),
+                        Ok(Async::NotReady) => {
+                            done = false;
+                            continue;
+                        }
+                        Err(_) => Entry::Failed(FanoutError::ClosedDialogue { index }),
+                    }
+                }
+                Entry::Failed(err) if self.propagate_errors => return Err(err),
+                Entry::Done(_) |
+                Entry::Failed(_) => continue,
+            };
+
+            if let Entry::Failed(err) = result {
+                if self.propagate_errors {
+                    return Err(err);
+                }
+            }
+            *entry = result;
+        }
+
+        if !done {
+            return Ok(Async::NotReady);
+        }
+
+        Ok(Async::Ready(mem::take(&mut self.entries)
+                            .into_iter()
+                            .map(|entry| match entry {
+                                     Entry::Done(result) => result,
+                                     Entry::Failed(_) => None,
+                                     Entry::Pending(_) => unreachable!(),
+                                 })
+                            .collect()))
+    }
+}
+
+/// The error of a `FanoutFuture` after calling `propagate_errors`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanoutError {
+    /// The `Dialogue` of the peer at the given index has been closed.
+    ClosedDialogue {
+        /// The index of the failing peer.
+        index: usize,
+    },
+    /// No request could be sent to the peer at the given index, because all
+    /// ids of its `Dialogue` are in use.
+    ExhaustedIds {
+        /// The index of the failing peer.
+        index: usize,
+    },
+}
+
+impl FanoutError {
+    /// Returns the index of the failing peer.
+    pub fn index(&self) -> usize {
+        match *self {
+            FanoutError::ClosedDialogue { index } |
+            FanoutError::ExhaustedIds { index } => index,
+        }
+    }
+}
+
+impl fmt::Display for FanoutError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FanoutError::ClosedDialogue { index } => write!(fmt, "ClosedDialogue: peer {}", index),
+            FanoutError::ExhaustedIds { index } => write!(fmt, "ExhaustedIds: peer {}", index),
+        }
+    }
+}
+
+impl Error for FanoutError {}
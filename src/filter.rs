@@ -0,0 +1,69 @@
+use futures::{Sink, Stream, Poll, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{Dialogue, Role};
+use transport_error::TransportError;
+
+/// A `Dialogue` whose `Stream` implementation only emits the packets accepted
+/// by a predicate, created via `Dialogue::recv_filter`.
+///
+/// Rejected packets are dropped. A rejected request is declined right away by
+/// sending a response without data. A rejected duplex is discarded right away:
+/// Its route is removed and this side's half is ended. Either way, the peer
+/// does not wait for an answer. Packets
+/// belonging to existing requests and duplexes never reach the predicate,
+/// they are routed by the wrapped `Dialogue` as usual.
+pub struct FilteredDialogue<P, T, SinkErr, StreamErr, Data, R, F> {
+    dialogue: Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    f: F,
+}
+
+impl<P, T, SinkErr, StreamErr, Data, R, F> FilteredDialogue<P, T, SinkErr, StreamErr, Data, R, F> {
+    pub(crate) fn new(dialogue: Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+                      f: F)
+                      -> FilteredDialogue<P, T, SinkErr, StreamErr, Data, R, F> {
+        FilteredDialogue { dialogue, f }
+    }
+
+    /// Returns a reference to the wrapped `Dialogue`.
+    pub fn get_ref(&self) -> &Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        &self.dialogue
+    }
+
+    /// Returns a mutable reference to the wrapped `Dialogue`, e.g. for sending
+    /// packets or handling emitted requests and duplexes.
+    pub fn get_mut(&mut self) -> &mut Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        &mut self.dialogue
+    }
+
+    /// Consumes the `FilteredDialogue`, returning the wrapped `Dialogue`.
+    pub fn into_inner(self) -> Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        self.dialogue
+    }
+}
+
+impl<P, T, SinkErr, StreamErr, Data, R, F> Stream
+    for FilteredDialogue<P, T, SinkErr, StreamErr, Data, R, F>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          F: Fn(&P) -> bool
+{
+    type Item = P;
+    type Error = TransportError<SinkErr, StreamErr>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.dialogue.poll()? {
+                Async::Ready(Some(packet)) => {
+                    if (self.f)(&packet) {
+                        return Ok(Async::Ready(Some(packet)));
+                    }
+
+                    self.dialogue.reject(&packet);
+                }
+                other => return Ok(other),
+            }
+        }
+    }
+}
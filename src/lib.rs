@@ -3,7 +3,40 @@ extern crate futures;
 mod packet;
 mod dialogue;
 mod transport_error;
+mod chunks;
+mod recv_exactly;
+mod on_close;
+mod fanout;
+mod filter;
+mod burst;
+mod validation;
+mod writer;
+mod sub_sink;
+mod sub_stream;
+mod sub_reduce_sink;
+mod sub_reduce_stream;
+mod sub_fold_sink;
+mod sub_fold_stream;
+mod timeout;
+
+pub mod prelude;
+pub mod testing;
 
 pub use packet::*;
 pub use dialogue::*;
 pub use transport_error::*;
+pub use chunks::*;
+pub use recv_exactly::*;
+pub use on_close::*;
+pub use fanout::*;
+pub use filter::*;
+pub use burst::*;
+pub use validation::*;
+pub use writer::*;
+pub use sub_sink::*;
+pub use sub_stream::*;
+pub use sub_reduce_sink::*;
+pub use sub_reduce_stream::*;
+pub use sub_fold_sink::*;
+pub use sub_fold_stream::*;
+pub use timeout::*;
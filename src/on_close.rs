@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use futures::{Sink, Stream, Poll, StartSend, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A `SubDuplex` which calls a function once the peer has ended the duplex,
+/// created via `SubDuplex::on_close`.
+///
+/// When the `Stream` implementation of the wrapped `SubDuplex` ends, the
+/// function is called with `None`. When it errors with
+/// `SubStreamError::EndWithError`, the function is called with the error data.
+/// This happens before the end or the error is emitted, and at most once.
+///
+/// The error data is passed as an `Arc<Data>`, because the same data is also
+/// emitted as `SubStreamError::EndWithError` afterwards. Sharing it means
+/// `Data` does not have to be `Clone`.
+///
+/// The `Sink` implementation is forwarded to the wrapped `SubDuplex`.
+pub struct OnCloseSubDuplex<'ps,
+                            P: 'ps,
+                            T: 'ps,
+                            SinkErr: 'ps,
+                            StreamErr: 'ps,
+                            Data: 'ps,
+                            R: 'ps,
+                            SubDuplexType: 'static,
+                            F>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+    f: Option<F>,
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     F> OnCloseSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, F>
+    where F: FnOnce(Option<Arc<Data>>)
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+                      f: F)
+                      -> OnCloseSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, F> {
+        OnCloseSubDuplex { duplex, f: Some(f) }
+    }
+
+    /// Returns a reference to the wrapped `SubDuplex`.
+    pub fn get_ref(&self) -> &SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        &self.duplex
+    }
+
+    /// Returns a mutable reference to the wrapped `SubDuplex`.
+    pub fn get_mut(&mut self)
+                   -> &mut SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        &mut self.duplex
+    }
+
+    fn notify(&mut self, data: Option<Arc<Data>>) {
+        if let Some(f) = self.f.take() {
+            f(data);
+        }
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static,
+     F> Stream for OnCloseSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, F>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          F: FnOnce(Option<Arc<Data>>)
+{
+    type Item = Data;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.duplex.poll() {
+            Ok(Async::Ready(None)) => {
+                self.notify(None);
+                Ok(Async::Ready(None))
+            }
+            Err(SubStreamError::EndWithError(data)) => {
+                self.notify(Some(data.clone()));
+                Err(SubStreamError::EndWithError(data))
+            }
+            other => other,
+        }
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     F> Sink for OnCloseSubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, F>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type SinkItem = Data;
+    type SinkError = ClosedDialogue;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.duplex.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.close()
+    }
+}
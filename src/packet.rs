@@ -1,3 +1,7 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
 /// Each packet has a PacketId, to identify it for multiplexing.
 pub type PacketId = u32;
 
@@ -14,6 +18,113 @@ pub enum PacketType {
     DuplexResponseEnd,
 }
 
+/// The largest byte which `PacketType::try_from` maps to a `PacketType`.
+pub const MAX_PACKET_TYPE_BYTE: u8 = 7;
+
+/// Maps the bytes `0` to `MAX_PACKET_TYPE_BYTE` to the `PacketType`s, in the
+/// order in which they are declared.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use dialogue::{PacketType, PacketTypeError};
+///
+/// assert_eq!(PacketType::try_from(1), Ok(PacketType::Request));
+///
+/// match PacketType::try_from(8) {
+///     Ok(_) => unreachable!(),
+///     Err(PacketTypeError::UnknownPacketType(byte)) => assert_eq!(byte, 8),
+///     // More errors may be added in the future.
+///     Err(_) => unreachable!(),
+/// }
+/// ```
+impl TryFrom<u8> for PacketType {
+    type Error = PacketTypeError;
+
+    fn try_from(byte: u8) -> Result<PacketType, PacketTypeError> {
+        match byte {
+            0 => Ok(PacketType::Message),
+            1 => Ok(PacketType::Request),
+            2 => Ok(PacketType::Response),
+            3 => Ok(PacketType::DuplexInitial),
+            4 => Ok(PacketType::DuplexRequest),
+            5 => Ok(PacketType::DuplexResponse),
+            6 => Ok(PacketType::DuplexRequestEnd),
+            7 => Ok(PacketType::DuplexResponseEnd),
+            _ => Err(PacketTypeError::UnknownPacketType(byte)),
+        }
+    }
+}
+
+/// The error for converting a byte into a `PacketType`.
+///
+/// More variants may be added in the future, e.g. for bytes reserved by
+/// later protocol versions.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PacketTypeError {
+    /// The byte does not denote any `PacketType`.
+    UnknownPacketType(u8),
+}
+
+impl fmt::Display for PacketTypeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PacketTypeError::UnknownPacketType(byte) => write!(fmt, "UnknownPacketType: {}", byte),
+        }
+    }
+}
+
+impl Error for PacketTypeError {}
+
+/// All `PacketType`s, ordered from highest to lowest scheduling priority.
+///
+/// Close-related packets, i.e. those ending a duplex, come first, then
+/// responses (so the peer does not wait longer than necessary), then packets
+/// initiating requests and duplexes, then duplex data, and messages last.
+///
+/// A `Dialogue` uses this order for the packets it queues while the transport
+/// is not ready. A packet never overtakes another packet with the same id, and
+/// the close and abort messages of the dialogue are never reordered.
+pub const PACKET_TYPE_PRIORITY: [PacketType; 8] = [PacketType::DuplexRequestEnd,
+                                                   PacketType::DuplexResponseEnd,
+                                                   PacketType::Response,
+                                                   PacketType::Request,
+                                                   PacketType::DuplexInitial,
+                                                   PacketType::DuplexRequest,
+                                                   PacketType::DuplexResponse,
+                                                   PacketType::Message];
+
+impl PacketType {
+    /// Returns the index of this `PacketType` in `PACKET_TYPE_PRIORITY`. Lower
+    /// values should be scheduled first.
+    pub fn scheduling_priority(&self) -> u8 {
+        PACKET_TYPE_PRIORITY
+            .iter()
+            .position(|t| t == self)
+            .expect("PACKET_TYPE_PRIORITY contains every PacketType") as u8
+    }
+
+    /// Returns whether packets of this type must carry data.
+    ///
+    /// For all other packet types, whether a packet carries data is part of
+    /// its meaning: An empty `Message` signals closing of the dialogue, an
+    /// empty `Request` cancels a request, an empty `Response` declines to
+    /// answer, and the end packets of a duplex carry data only to signal an
+    /// error.
+    pub fn must_carry_data(&self) -> bool {
+        match *self {
+            PacketType::DuplexInitial |
+            PacketType::DuplexRequest |
+            PacketType::DuplexResponse => true,
+            PacketType::Message |
+            PacketType::Request |
+            PacketType::Response |
+            PacketType::DuplexRequestEnd |
+            PacketType::DuplexResponseEnd => false,
+        }
+    }
+}
+
 /// Values implementing this trait can be sent via a `Dialogue`.
 pub trait PacketWritable {
     /// The data carried by the packet.
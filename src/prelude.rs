@@ -0,0 +1,41 @@
+//! Re-exports of the commonly used traits and roles, and type aliases for
+//! dialogues over transports whose errors are `io::Error` and whose packets
+//! carry `Vec<u8>`.
+//!
+//! ```
+//! use dialogue::prelude::*;
+//!
+//! struct Connection<'ps, P: 'ps, T: 'ps> {
+//!     pending: Option<IoResponse<'ps, P, T, Client>>,
+//! }
+//!
+//! struct Handler<'ps, P: 'ps, T: 'ps> {
+//!     request: Option<IoRequest<'ps, P, T, Server>>,
+//!     duplex: Option<IoSubDuplex<'ps, P, T, Server, InSubDuplex>>,
+//! }
+//!
+//! struct Peer<P, T> {
+//!     dialogue: IoDialogue<P, T, Client>,
+//! }
+//! ```
+
+use std::io;
+
+use dialogue::{Dialogue, Request, Response, SubDuplex};
+
+pub use packet::{PacketWritable, PacketReadable, PacketId, PacketType};
+pub use dialogue::{Role, Server, Client, SubDuplexType, OutSubDuplex, InSubDuplex};
+pub use validation::PacketValidator;
+pub use timeout::Timer;
+
+/// A `Dialogue` over a transport with `io::Error`s, exchanging `Vec<u8>`.
+pub type IoDialogue<P, T, R> = Dialogue<P, T, io::Error, io::Error, Vec<u8>, R>;
+
+/// A `Request` of an `IoDialogue`.
+pub type IoRequest<'ps, P, T, R> = Request<'ps, P, T, io::Error, io::Error, Vec<u8>, R>;
+
+/// A `Response` of an `IoDialogue`.
+pub type IoResponse<'ps, P, T, R> = Response<'ps, P, T, io::Error, io::Error, Vec<u8>, R>;
+
+/// A `SubDuplex` of an `IoDialogue`.
+pub type IoSubDuplex<'ps, P, T, R, S> = SubDuplex<'ps, P, T, io::Error, io::Error, Vec<u8>, R, S>;
@@ -0,0 +1,217 @@
+use std::mem;
+use std::fmt;
+use std::error::Error;
+
+use futures::{Future, Sink, Stream, Poll, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{Dialogue, SubDuplex, SubStreamError, Role};
+use transport_error::TransportError;
+
+/// A future which receives a fixed number of packets from a `Dialogue`,
+/// created via `Dialogue::recv_exactly`.
+///
+/// Resolves to the received packets once `n` packets have been emitted by the
+/// `Stream` implementation of the `Dialogue`.
+///
+/// Unlike when polling the `Dialogue` directly, the duplexes initiated by
+/// received `DuplexInitial` packets are not discarded when the next packet is
+/// received. Each of them stays open until its packet is passed to
+/// `Dialogue::packet_as_sub_duplex` (or one of its variants) and the resulting
+/// handle is dropped.
+pub struct RecvExactly<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> {
+    dialogue: &'d mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    packets: Vec<P>,
+    expected: usize,
+}
+
+impl<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> RecvExactly<'d,
+                                                                                P,
+                                                                                T,
+                                                                                SinkErr,
+                                                                                StreamErr,
+                                                                                Data,
+                                                                                R> {
+    pub(crate) fn new(dialogue: &'d mut Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+                      n: usize)
+                      -> RecvExactly<'d, P, T, SinkErr, StreamErr, Data, R> {
+        RecvExactly {
+            dialogue,
+            packets: Vec::with_capacity(n),
+            expected: n,
+        }
+    }
+}
+
+impl<'d, P: 'd, T: 'd, SinkErr: 'd, StreamErr: 'd, Data: 'd, R: 'd> Future
+    for
+    RecvExactly<'d, P, T, SinkErr, StreamErr, Data, R>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Vec<P>;
+    type Error = RecvExactlyError<SinkErr, StreamErr>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while self.packets.len() < self.expected {
+            match self.dialogue.poll().map_err(RecvExactlyError::TransportError)? {
+                Async::Ready(Some(packet)) => {
+                    self.dialogue.keep_unaccepted();
+                    self.packets.push(packet);
+                }
+                Async::Ready(None) => {
+                    return Err(RecvExactlyError::StreamEndedEarly {
+                                   received: self.packets.len(),
+                                   expected: self.expected,
+                               })
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        Ok(Async::Ready(mem::take(&mut self.packets)))
+    }
+}
+
+/// The error of a `RecvExactly` future.
+#[derive(Debug)]
+pub enum RecvExactlyError<SinkErr, StreamErr> {
+    /// The `Dialogue` emitted an error.
+    TransportError(TransportError<SinkErr, StreamErr>),
+    /// The `Dialogue` ended before the expected number of packets was received.
+    StreamEndedEarly {
+        /// The number of packets received before the `Dialogue` ended.
+        received: usize,
+        /// The number of packets that should have been received.
+        expected: usize,
+    },
+}
+
+impl<SinkErr: fmt::Display, StreamErr: fmt::Display> fmt::Display
+    for RecvExactlyError<SinkErr, StreamErr> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecvExactlyError::TransportError(ref e) => write!(fmt, "TransportError: {}", e),
+            RecvExactlyError::StreamEndedEarly { received, expected } => {
+                write!(fmt,
+                       "StreamEndedEarly: received {} of {} packets",
+                       received,
+                       expected)
+            }
+        }
+    }
+}
+
+impl<SinkErr: Error + 'static, StreamErr: Error + 'static> Error
+    for RecvExactlyError<SinkErr, StreamErr> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RecvExactlyError::TransportError(ref e) => Some(e),
+            RecvExactlyError::StreamEndedEarly { .. } => None,
+        }
+    }
+}
+
+/// A future which receives a fixed number of items from a `SubDuplex`,
+/// created via `SubDuplex::expect_exactly`.
+///
+/// Resolves to the received items once `n` items have been received, then
+/// drops the `SubDuplex`, ignoring any further items. Errors with
+/// `SubStreamError::TooFewItems` if the duplex ends before `n` items were
+/// received.
+///
+/// A `strict` future does not ignore surplus items: After the `n`th item, it
+/// waits for the duplex to end, and errors with `SubStreamError::TooManyItems`
+/// if another item arrives instead.
+pub struct ExpectExactly<'ps,
+                         P: 'ps,
+                         T: 'ps,
+                         SinkErr: 'ps,
+                         StreamErr: 'ps,
+                         Data: 'ps,
+                         R: 'ps,
+                         SubDuplexType: 'static>
+{
+    duplex: Option<SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>>,
+    items: Vec<Data>,
+    expected: usize,
+    strict: bool,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    ExpectExactly<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+                      n: usize)
+                      -> ExpectExactly<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        ExpectExactly {
+            duplex: Some(duplex),
+            items: Vec::with_capacity(n),
+            expected: n,
+            strict: false,
+        }
+    }
+
+    /// Makes this future error with `SubStreamError::TooManyItems` if the
+    /// duplex yields more than the expected number of items.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static> Future
+    for
+    ExpectExactly<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Vec<Data>;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while self.items.len() < self.expected {
+            let duplex = self.duplex
+                .as_mut()
+                .expect("polled ExpectExactly after completion");
+
+            match duplex.poll()? {
+                Async::Ready(Some(item)) => self.items.push(item),
+                Async::Ready(None) => {
+                    self.duplex = None;
+                    return Err(SubStreamError::TooFewItems {
+                                   received: self.items.len(),
+                                   expected: self.expected,
+                               });
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        if self.strict {
+            let duplex = self.duplex
+                .as_mut()
+                .expect("polled ExpectExactly after completion");
+
+            match duplex.poll()? {
+                Async::Ready(Some(_)) => {
+                    self.duplex = None;
+                    return Err(SubStreamError::TooManyItems { expected: self.expected });
+                }
+                Async::Ready(None) => {}
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        self.duplex = None;
+        Ok(Async::Ready(mem::take(&mut self.items)))
+    }
+}
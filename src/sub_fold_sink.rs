@@ -0,0 +1,205 @@
+use futures::{Sink, Stream, Poll, StartSend, Async, AsyncSink};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, ClosedDialogue, Role};
+
+/// A write-only duplex which folds all items it sends into an accumulated
+/// value, and sends that value to the peer when it is closed.
+///
+/// Each item is passed to the fold function before it is sent. On the wire
+/// this is a regular duplex: The items are followed by the accumulated value
+/// as the last item, and a regular end packet. The peer receives the
+/// accumulated value via a `SubFoldStream`.
+///
+/// Dropping the sink without closing it ends the duplex with an error, whose
+/// data is the value accumulated so far. This way, the peer's `SubFoldStream`
+/// errors instead of mistaking the last item for the accumulated value.
+///
+/// Created via `Dialogue::sub_fold_sink`.
+///
+/// ```
+/// extern crate futures;
+/// extern crate dialogue;
+///
+/// use std::thread;
+///
+/// use futures::{Future, Sink, Stream};
+/// use futures::future::poll_fn;
+/// use dialogue::Dialogue;
+/// use dialogue::testing::{paired_transports, TestDialogue};
+///
+/// let (a, b) = paired_transports();
+/// let mut client: TestDialogue<_> = Dialogue::client(a);
+/// let mut server: TestDialogue<_> = Dialogue::server(b);
+///
+/// let receiving = thread::spawn(move || {
+///     let packet = poll_fn(|| server.poll()).wait().unwrap().unwrap();
+///     let total: Option<Vec<u8>> = server.packet_as_sub_fold_stream(packet).wait().unwrap();
+///     total
+/// });
+///
+/// // The client sends numbers, and the sum of all of them once it is done.
+/// let mut sink = client
+///     .sub_fold_sink(vec![0], |sum, item| vec![sum[0] + item[0]], b"sum".to_vec())
+///     .unwrap();
+/// for n in 1..11 {
+///     sink.start_send(vec![n]).unwrap();
+/// }
+/// poll_fn(|| sink.close()).wait().unwrap();
+///
+/// assert_eq!(receiving.join().unwrap(), Some(vec![55]));
+/// ```
+pub struct SubFoldSink<'ps,
+                       P: 'ps,
+                       T: 'ps,
+                       SinkErr: 'ps,
+                       StreamErr: 'ps,
+                       Data: 'ps,
+                       R: 'ps,
+                       SubDuplexType: 'static,
+                       A,
+                       F>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+    // `None` once the accumulated value has been sent.
+    acc: Option<A>,
+    f: F,
+    // An item that has been folded, but not yet accepted by the duplex.
+    buffered: Option<Data>,
+    // Converts the accumulated value, stored so that the `Drop` implementation
+    // can send it although it has no trait bounds.
+    acc_into_data: fn(A) -> Data,
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     A,
+     F> SubFoldSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A, F>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+                      init: A,
+                      f: F)
+                      -> SubFoldSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A, F>
+        where A: Into<Data>
+    {
+        SubFoldSink {
+            duplex,
+            acc: Some(init),
+            f,
+            buffered: None,
+            acc_into_data: A::into,
+        }
+    }
+
+    /// Returns the value accumulated so far, or `None` if it has already been
+    /// sent.
+    pub fn get_acc(&self) -> Option<&A> {
+        self.acc.as_ref()
+    }
+
+    /// Directly close the sink without sending the accumulated value, not
+    /// waiting for the peer, which is given some error data.
+    ///
+    /// There is no way to abort without error data: The peer could not tell
+    /// the last item apart from the accumulated value.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.acc = None;
+        self.buffered = None;
+        self.duplex.abort_error(err)
+    }
+
+    /// Passes the buffered item to the duplex.
+    fn send_buffered(&mut self) -> Poll<(), ClosedDialogue> {
+        if let Some(item) = self.buffered.take() {
+            if let AsyncSink::NotReady(item) = self.duplex.start_send(item)? {
+                self.buffered = Some(item);
+                return Ok(Async::NotReady);
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+/// Closing sends the accumulated value, then performs a half-close of the
+/// duplex like `SubDuplex::close`.
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     A,
+     F> Sink for SubFoldSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A, F>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          A: Into<Data>,
+          F: Fn(A, &Data) -> A
+{
+    type SinkItem = Data;
+    type SinkError = ClosedDialogue;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if self.send_buffered()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        let acc = self.acc.take().ok_or(ClosedDialogue)?;
+        self.acc = Some((self.f)(acc, &item));
+
+        // The item has been folded already, so it is accepted even if the
+        // duplex is not ready for it yet.
+        if let AsyncSink::NotReady(item) = self.duplex.start_send(item)? {
+            self.buffered = Some(item);
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        if self.send_buffered()?.is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+        self.duplex.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        if self.send_buffered()?.is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+
+        if let Some(acc) = self.acc.take() {
+            self.duplex.end_with_item(acc.into())?;
+        }
+        self.duplex.close()
+    }
+}
+
+/// Ends the duplex with an error if the accumulated value has not been sent.
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     A,
+     F> Drop for SubFoldSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A, F> {
+    fn drop(&mut self) {
+        if let Some(acc) = self.acc.take() {
+            // If the dialogue has closed, the peer does not wait for the end.
+            let _ = self.duplex.end_with_error((self.acc_into_data)(acc));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::marker::PhantomData;
+
+use futures::{Future, Sink, Stream, Poll, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A read-only duplex which resolves to the value accumulated by the peer's
+/// `SubFoldSink`.
+///
+/// It implements `Future`, resolving once the peer ended the duplex: to the
+/// last item the peer sent, which is the accumulated value, or to `None` if
+/// the peer sent no items at all. All other items are skipped. This side's
+/// half of the duplex is ended before the future resolves. See `SubFoldSink`
+/// for an example.
+///
+/// Created via `Dialogue::packet_as_sub_fold_stream`.
+pub struct SubFoldStream<'ps,
+                         P: 'ps,
+                         T: 'ps,
+                         SinkErr: 'ps,
+                         StreamErr: 'ps,
+                         Data: 'ps,
+                         R: 'ps,
+                         SubDuplexType: 'static,
+                         A>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+    // The last item received so far.
+    last: Option<Data>,
+    // Set once the peer ended its half of the duplex.
+    ended: bool,
+    acc_type: PhantomData<fn() -> A>,
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static,
+     A> SubFoldStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>)
+                      -> SubFoldStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A> {
+        SubFoldStream {
+            duplex,
+            last: None,
+            ended: false,
+            acc_type: PhantomData,
+        }
+    }
+
+    /// Directly cancel the stream (without error), dropping any outstanding
+    /// items.
+    pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort()
+    }
+
+    /// Same as `abort`, but the peer is given some error data.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort_error(err)
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static,
+     A> Future for SubFoldStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType, A>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          A: From<Data>
+{
+    type Item = Option<A>;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while !self.ended {
+            match self.duplex.poll()? {
+                Async::Ready(Some(item)) => self.last = Some(item),
+                Async::Ready(None) => self.ended = true,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        match self.duplex.close() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(self.last.take().map(A::from))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(ClosedDialogue) => Err(SubStreamError::ClosedDialogue),
+        }
+    }
+}
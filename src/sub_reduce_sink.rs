@@ -0,0 +1,157 @@
+use futures::{Future, Sink, Stream, Poll, StartSend, Async};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A write-only duplex whose items are reduced to a single value by the peer:
+/// This side only sends items, the peer answers with one value once this side
+/// ended the duplex.
+///
+/// On the wire this is a regular duplex, only the interface is restricted. The
+/// peer sends the reduced value as its only item, followed by a regular end
+/// packet.
+///
+/// It implements `Sink` for the items, and `Future` for the reduced value.
+/// Polling the future closes the sink if that has not happened yet, so the
+/// reduced value is never yielded before the sink has been closed. The future
+/// errors with `SubStreamError::EndWithError` if the peer ends the duplex with
+/// an error, and with `SubStreamError::TooFewItems` if the peer ends it without
+/// a value.
+///
+/// Created via `Dialogue::sub_reduce_sink` or
+/// `Dialogue::packet_as_sub_reduce_sink`.
+///
+/// ```
+/// extern crate futures;
+/// extern crate dialogue;
+///
+/// use std::thread;
+///
+/// use futures::{Future, Sink, Stream};
+/// use futures::future::poll_fn;
+/// use dialogue::Dialogue;
+/// use dialogue::testing::{paired_transports, TestDialogue};
+///
+/// let (a, b) = paired_transports();
+/// let mut client: TestDialogue<_> = Dialogue::client(a);
+/// let mut server: TestDialogue<_> = Dialogue::server(b);
+///
+/// // The server sums up all numbers it receives.
+/// let summing = thread::spawn(move || {
+///     let packet = poll_fn(|| server.poll()).wait().unwrap().unwrap();
+///     let mut stream = server.packet_as_sub_reduce_stream(packet);
+///
+///     let sum = (&mut stream).fold(0, |sum, item| Ok::<u8, _>(sum + item[0])).wait().unwrap();
+///     stream.finish(vec![sum]).unwrap();
+///     poll_fn(|| stream.poll_complete()).wait().unwrap();
+/// });
+///
+/// let mut sink = client.sub_reduce_sink(b"sum".to_vec()).unwrap();
+/// for n in 1..11 {
+///     sink.start_send(vec![n]).unwrap();
+/// }
+///
+/// assert_eq!(Future::wait(sink).unwrap(), vec![55]);
+/// summing.join().unwrap();
+/// ```
+pub struct SubReduceSink<'ps,
+                         P: 'ps,
+                         T: 'ps,
+                         SinkErr: 'ps,
+                         StreamErr: 'ps,
+                         Data: 'ps,
+                         R: 'ps,
+                         SubDuplexType: 'static>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    SubReduceSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>)
+                      -> SubReduceSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        SubReduceSink { duplex }
+    }
+
+    /// Same as `close`, but the receiving stream is given some error data.
+    pub fn close_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.close_error(err)
+    }
+
+    /// Directly close the sink (without error), not waiting for the reduced
+    /// value.
+    pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort()
+    }
+
+    /// Same as `abort`, but the receiving stream is given some error data.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort_error(err)
+    }
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static> Sink
+    for
+    SubReduceSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type SinkItem = Data;
+    type SinkError = ClosedDialogue;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.duplex.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.close()
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static> Future
+    for
+    SubReduceSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Data;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Closing completes once the peer ended its half of the duplex, so the
+        // reduced value has been received by then.
+        match self.duplex.close() {
+            Ok(Async::Ready(())) => {}
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(ClosedDialogue) => return Err(SubStreamError::ClosedDialogue),
+        }
+
+        match self.duplex.poll()? {
+            Async::Ready(Some(value)) => Ok(Async::Ready(value)),
+            Async::Ready(None) => {
+                Err(SubStreamError::TooFewItems {
+                        received: 0,
+                        expected: 1,
+                    })
+            }
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
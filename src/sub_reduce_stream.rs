@@ -0,0 +1,89 @@
+use futures::{Sink, Stream, Poll};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A read-only duplex whose items are reduced to a single value by this side:
+/// The peer only sends items, this side answers with one value once the peer
+/// ended the duplex.
+///
+/// On the wire this is a regular duplex, only the interface is restricted. It
+/// implements `Stream` for the items, the reduced value is sent via `finish`,
+/// as the only item of this side followed by a regular end packet. See
+/// `SubReduceSink` for an example.
+///
+/// Created via `Dialogue::sub_reduce_stream` or
+/// `Dialogue::packet_as_sub_reduce_stream`.
+pub struct SubReduceStream<'ps,
+                           P: 'ps,
+                           T: 'ps,
+                           SinkErr: 'ps,
+                           StreamErr: 'ps,
+                           Data: 'ps,
+                           R: 'ps,
+                           SubDuplexType: 'static>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    SubReduceStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>)
+                      -> SubReduceStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        SubReduceStream { duplex }
+    }
+
+    /// Sends the reduced value to the peer and ends the duplex. Call this once
+    /// the stream has ended.
+    ///
+    /// An error is returned if the dialogue has closed or if the duplex has
+    /// already been finished or aborted. The packets are queued in the
+    /// `Dialogue` otherwise, to make sure they have actually been sent, call
+    /// `poll_complete`.
+    pub fn finish(&mut self, value: Data) -> Result<(), ClosedDialogue> {
+        self.duplex.end_with_item(value)
+    }
+
+    /// Delegates to the `poll_complete` method of the `Dialogue`.
+    pub fn poll_complete(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.poll_complete()
+    }
+
+    /// Directly cancel the stream (without error), dropping any outstanding
+    /// items. The peer does not receive a reduced value.
+    pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort()
+    }
+
+    /// Same as `abort`, but the sending peer is given some error data instead
+    /// of a reduced value.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort_error(err)
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static> Stream
+    for
+    SubReduceStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Data;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.duplex.poll()
+    }
+}
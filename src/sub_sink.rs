@@ -0,0 +1,75 @@
+use futures::{Sink, Stream, Poll, StartSend};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, ClosedDialogue, Role};
+
+/// A write-only duplex: This side only sends items, the peer only receives
+/// them.
+///
+/// On the wire this is a regular duplex, only the interface is restricted. It
+/// implements `Sink` but not `Stream`. Closing it sends this side's end packet
+/// and waits for the peer to end its half of the duplex.
+///
+/// Created via `Dialogue::sub_sink` or `Dialogue::packet_as_sub_sink`.
+pub struct SubSink<'ps,
+                   P: 'ps,
+                   T: 'ps,
+                   SinkErr: 'ps,
+                   StreamErr: 'ps,
+                   Data: 'ps,
+                   R: 'ps,
+                   SubDuplexType: 'static>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    SubSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>)
+                      -> SubSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        SubSink { duplex }
+    }
+
+    /// Same as `close`, but the receiving stream is given some error data.
+    pub fn close_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.close_error(err)
+    }
+
+    /// Directly close the sink (without error), not waiting for confirmation
+    /// by the peer.
+    pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort()
+    }
+
+    /// Same as `abort`, but the receiving stream is given some error data.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort_error(err)
+    }
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static> Sink
+    for
+    SubSink<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type SinkItem = Data;
+    type SinkError = ClosedDialogue;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.duplex.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.duplex.close()
+    }
+}
@@ -0,0 +1,69 @@
+use futures::{Sink, Stream, Poll};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, SubStreamError, ClosedDialogue, Role};
+
+/// A read-only duplex: This side only receives items, the peer only sends
+/// them.
+///
+/// On the wire this is a regular duplex, only the interface is restricted. It
+/// implements `Stream` but not `Sink`. Dropping it sends this side's end packet,
+/// so drop it once the stream has ended or to cancel it.
+///
+/// Created via `Dialogue::sub_stream` or `Dialogue::packet_as_sub_stream`.
+pub struct SubStream<'ps,
+                     P: 'ps,
+                     T: 'ps,
+                     SinkErr: 'ps,
+                     StreamErr: 'ps,
+                     Data: 'ps,
+                     R: 'ps,
+                     SubDuplexType: 'static>
+{
+    duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, SubDuplexType: 'static>
+    SubStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    pub(crate) fn new(duplex: SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>)
+                      -> SubStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+        SubStream { duplex }
+    }
+
+    /// Directly cancel the stream (without error), dropping any outstanding
+    /// items.
+    pub fn abort(&mut self) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort()
+    }
+
+    /// Same as `abort`, but the sending peer is given some error data.
+    pub fn abort_error(&mut self, err: Data) -> Poll<(), ClosedDialogue> {
+        self.duplex.abort_error(err)
+    }
+}
+
+impl<'ps,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'static,
+     SubDuplexType: 'static> Stream
+    for
+    SubStream<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role
+{
+    type Item = Data;
+    type Error = SubStreamError<Data>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.duplex.poll()
+    }
+}
@@ -0,0 +1,164 @@
+//! Helpers for testing code which uses a `Dialogue`, without a real network
+//! transport.
+//!
+//! ```
+//! extern crate futures;
+//! extern crate dialogue;
+//!
+//! use futures::{Future, Stream};
+//! use futures::future::poll_fn;
+//! use dialogue::{Dialogue, PacketReadable, PacketType};
+//! use dialogue::testing::{paired_transports, TestDialogue};
+//!
+//! let (a, b) = paired_transports();
+//! let mut client: TestDialogue<_> = Dialogue::client(a);
+//! let mut server: TestDialogue<_> = Dialogue::server(b);
+//!
+//! // A message from the client to the server.
+//! client.message(b"hello".to_vec()).unwrap();
+//! poll_fn(|| client.poll_complete()).wait().unwrap();
+//!
+//! let packet = poll_fn(|| server.poll()).wait().unwrap().unwrap();
+//! assert_eq!(packet.get_type(), PacketType::Message);
+//! assert_eq!(packet.get_data(), Some(b"hello".to_vec()));
+//!
+//! // A request from the client, answered by the server.
+//! let mut response = client.request(b"ping".to_vec()).unwrap();
+//! poll_fn(|| response.poll_complete()).wait().unwrap();
+//!
+//! let packet = poll_fn(|| server.poll()).wait().unwrap().unwrap();
+//! server.packet_as_request(packet).start_responding(b"pong".to_vec()).unwrap();
+//! poll_fn(|| server.poll_complete()).wait().unwrap();
+//!
+//! assert_eq!(response.wait().unwrap(), Some(b"pong".to_vec()));
+//! ```
+
+use std::fmt;
+use std::error::Error;
+
+use futures::{Sink, Stream, Poll, StartSend, Async, AsyncSink};
+use futures::sync::mpsc::{self, UnboundedSender, UnboundedReceiver};
+
+use packet::{PacketWritable, PacketReadable, PacketId, PacketType};
+use dialogue::Dialogue;
+
+/// A `Dialogue` over a `PairedTransport`, exchanging `ConcretePacket`s.
+pub type TestDialogue<R> = Dialogue<ConcretePacket,
+                                    PairedTransport<ConcretePacket>,
+                                    Never,
+                                    Never,
+                                    Vec<u8>,
+                                    R>;
+
+/// Creates two connected in-memory transports: Packets sent into one of them
+/// are emitted by the other one.
+pub fn paired_transports<P>() -> (PairedTransport<P>, PairedTransport<P>) {
+    let (sender_a, receiver_b) = mpsc::unbounded();
+    let (sender_b, receiver_a) = mpsc::unbounded();
+
+    (PairedTransport {
+         sender: sender_a,
+         receiver: receiver_a,
+     },
+     PairedTransport {
+         sender: sender_b,
+         receiver: receiver_b,
+     })
+}
+
+/// One end of an in-memory transport, created via `paired_transports`.
+///
+/// It never errors: Packets sent after the other end has been dropped are
+/// discarded, and the `Stream` implementation ends once the other end has
+/// been dropped.
+#[derive(Debug)]
+pub struct PairedTransport<P> {
+    sender: UnboundedSender<P>,
+    receiver: UnboundedReceiver<P>,
+}
+
+impl<P> Sink for PairedTransport<P> {
+    type SinkItem = P;
+    type SinkError = Never;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        // An unbounded sender is always ready, it only errors if the other end
+        // is gone.
+        let _ = self.sender.unbounded_send(item);
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<P> Stream for PairedTransport<P> {
+    type Item = P;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        // The receiver of a channel never errors.
+        Ok(self.receiver.poll().unwrap_or(Async::Ready(None)))
+    }
+}
+
+/// An error which can never occur.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Never {}
+
+impl fmt::Display for Never {
+    fn fmt(&self, _fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl Error for Never {}
+
+/// A simple packet carrying a `Vec<u8>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConcretePacket {
+    id: PacketId,
+    packet_type: PacketType,
+    data: Option<Vec<u8>>,
+}
+
+impl PacketWritable for ConcretePacket {
+    type Data = Vec<u8>;
+
+    fn set_id(&mut self, id: PacketId) {
+        self.id = id;
+    }
+
+    fn set_type(&mut self, t: PacketType) {
+        self.packet_type = t;
+    }
+
+    fn new(data: Option<Vec<u8>>) -> ConcretePacket {
+        ConcretePacket {
+            id: 0,
+            packet_type: PacketType::Message,
+            data,
+        }
+    }
+}
+
+impl PacketReadable for ConcretePacket {
+    type Data = Vec<u8>;
+
+    fn get_id(&self) -> PacketId {
+        self.id
+    }
+
+    fn get_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    fn get_data(&self) -> Option<Vec<u8>> {
+        self.data.clone()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_none()
+    }
+}
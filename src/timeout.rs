@@ -0,0 +1,145 @@
+use std::fmt;
+use std::error::Error;
+use std::time::Instant;
+
+use futures::{Future, Sink, Stream, Poll, Async};
+
+use packet::{PacketWritable, PacketReadable, PacketId};
+use dialogue::{Response, Role};
+
+/// A `Response` which gives up once a delay future resolves, created via
+/// `Response::with_timeout`, `Response::with_deadline` or
+/// `Dialogue::timed_request`.
+///
+/// The delay can be any future resolving at the deadline, e.g. a timer of the
+/// event loop. If it resolves before the response arrives, the original request
+/// is cancelled and the future errors with `TimedResponseError::Timeout`. The
+/// cancellation is queued in the `Dialogue`, call its `poll_complete` to send
+/// it.
+///
+/// A plain `Response` waits until the peer answers or the `Dialogue` closes,
+/// the default request timeout only applies to `Dialogue::timed_request`.
+pub struct TimedResponse<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, D> {
+    response: Response<'ps, P, T, SinkErr, StreamErr, Data, R>,
+    // `None` if the response never times out.
+    delay: Option<D>,
+    // Set once the delay resolved and the request has been cancelled.
+    timed_out: bool,
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, D>
+    TimedResponse<'ps, P, T, SinkErr, StreamErr, Data, R, D> {
+    pub(crate) fn new(response: Response<'ps, P, T, SinkErr, StreamErr, Data, R>,
+                      delay: Option<D>)
+                      -> TimedResponse<'ps, P, T, SinkErr, StreamErr, Data, R, D> {
+        TimedResponse {
+            response,
+            delay,
+            timed_out: false,
+        }
+    }
+}
+
+impl<'ps, P: 'ps, T: 'ps, SinkErr: 'ps, StreamErr: 'ps, Data: 'ps, R: 'ps, D> Future
+    for
+    TimedResponse<'ps, P, T, SinkErr, StreamErr, Data, R, D>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          D: Future<Item = ()>
+{
+    type Item = Option<Data>;
+    type Error = TimedResponseError<D::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.timed_out {
+            return Err(TimedResponseError::Timeout(ResponseTimeout { id: self.response.id() }));
+        }
+
+        match self.response.poll() {
+            Ok(Async::Ready(data)) => return Ok(Async::Ready(data)),
+            Ok(Async::NotReady) => {}
+            Err(_) => return Err(TimedResponseError::ClosedDialogue),
+        }
+
+        match self.delay.as_mut().map(Future::poll) {
+            Some(Ok(Async::Ready(()))) => {}
+            Some(Ok(Async::NotReady)) | None => return Ok(Async::NotReady),
+            Some(Err(err)) => return Err(TimedResponseError::Delay(err)),
+        }
+
+        // If the dialogue has closed, there is nothing left to cancel.
+        let _ = self.response.cancel();
+        self.timed_out = true;
+        Err(TimedResponseError::Timeout(ResponseTimeout { id: self.response.id() }))
+    }
+}
+
+/// Creates the delay futures for `Response::with_deadline` and
+/// `Dialogue::timed_request`, so that any timer implementation can be plugged
+/// in.
+pub trait Timer {
+    /// The future resolving at a deadline.
+    type Delay: Future<Item = ()>;
+
+    /// Returns the current time of this timer's clock, used to compute the
+    /// deadline of `Dialogue::timed_request`.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Returns a future which resolves at the given `deadline`.
+    fn delay_until(&self, deadline: Instant) -> Self::Delay;
+}
+
+/// The error of a `TimedResponse`.
+#[derive(Debug)]
+pub enum TimedResponseError<DelayErr> {
+    /// The corresponding dialogue has been closed.
+    ClosedDialogue,
+    /// No response arrived before the delay resolved.
+    Timeout(ResponseTimeout),
+    /// The delay future errored.
+    Delay(DelayErr),
+}
+
+impl<DelayErr: fmt::Display> fmt::Display for TimedResponseError<DelayErr> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimedResponseError::ClosedDialogue => write!(fmt, "ClosedDialogue"),
+            TimedResponseError::Timeout(ref timeout) => write!(fmt, "{}", timeout),
+            TimedResponseError::Delay(ref err) => write!(fmt, "DelayError: {}", err),
+        }
+    }
+}
+
+impl<DelayErr: Error + 'static> Error for TimedResponseError<DelayErr> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TimedResponseError::ClosedDialogue => None,
+            TimedResponseError::Timeout(ref timeout) => Some(timeout),
+            TimedResponseError::Delay(ref err) => Some(err),
+        }
+    }
+}
+
+/// An error indicating that no response to a request arrived in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTimeout {
+    id: PacketId,
+}
+
+impl ResponseTimeout {
+    /// The `PacketId` of the request that timed out.
+    pub fn id(&self) -> PacketId {
+        self.id
+    }
+}
+
+impl fmt::Display for ResponseTimeout {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "ResponseTimeout: request {}", self.id)
+    }
+}
+
+impl Error for ResponseTimeout {}
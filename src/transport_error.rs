@@ -1,31 +1,166 @@
 use std::fmt;
 use std::error::Error;
+use std::io;
+
+use validation::ValidationError;
 
 /// A transport error: Either an error emitted by the `Sink` implementation of
-/// a transport, or by the `Stream` implementation.
+/// a transport, or by the `Stream` implementation, or an incoming packet that
+/// failed validation.
 #[derive(Debug)]
 pub enum TransportError<SinkErr, StreamErr> {
     /// An error originating from a `Sink` implementation.
     SinkError(SinkErr),
     /// An error originating from a `Stream` implementation.
     StreamError(StreamErr),
+    /// An incoming packet was rejected by a `PacketValidator`.
+    ValidationError(ValidationError),
 }
 
-impl<SinkErr: fmt::Display, StreamErr: fmt::Display> fmt::Display
-    for TransportError<SinkErr, StreamErr> {
+/// Only names the variant, the wrapped error is available via `Error::source`.
+impl<SinkErr, StreamErr> fmt::Display for TransportError<SinkErr, StreamErr> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            TransportError::SinkError(ref e) => write!(fmt, "SinkError: {}", e),
-            TransportError::StreamError(ref e) => write!(fmt, "StreamError: {}", e),
+            TransportError::SinkError(_) => write!(fmt, "SinkError"),
+            TransportError::StreamError(_) => write!(fmt, "StreamError"),
+            TransportError::ValidationError(_) => write!(fmt, "ValidationError"),
         }
     }
 }
 
-impl<SinkErr: Error, StreamErr: Error> Error for TransportError<SinkErr, StreamErr> {
-    fn description(&self) -> &str {
+/// The wrapped error is returned by `source`, which requires both error types
+/// to be `'static`: A `TransportError` over non-`'static` error types does not
+/// implement `Error`. The deprecated `description` is not overridden, use
+/// `Display` and `source` instead.
+impl<SinkErr: Error + 'static, StreamErr: Error + 'static> Error
+    for TransportError<SinkErr, StreamErr> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
-            TransportError::SinkError(ref e) => e.description(),
-            TransportError::StreamError(ref e) => e.description(),
+            TransportError::SinkError(ref e) => Some(e),
+            TransportError::StreamError(ref e) => Some(e),
+            TransportError::ValidationError(ref e) => Some(e),
         }
     }
 }
+
+/// The half of a transport an error originated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportDirection {
+    /// The `Sink` half of the transport.
+    Sink,
+    /// The `Stream` half of the transport.
+    Stream,
+}
+
+/// The payload of an `io::Error` created from a `TransportError::SinkError`
+/// or a `TransportError::StreamError`, recording which half of the transport
+/// the error originated from.
+///
+/// Retrieve it by downcasting the `io::Error`'s inner error, or convert the
+/// `io::Error` back via `TransportError::try_from_io`.
+#[derive(Debug)]
+pub struct TransportIoError {
+    direction: TransportDirection,
+    error: io::Error,
+}
+
+impl TransportIoError {
+    /// The half of the transport the error originated from.
+    pub fn direction(&self) -> TransportDirection {
+        self.direction
+    }
+
+    /// Gets a reference to the original error.
+    pub fn get_ref(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Consumes this payload, returning the original error.
+    pub fn into_inner(self) -> io::Error {
+        self.error
+    }
+}
+
+/// Only names the direction, the original error is available via
+/// `Error::source`.
+impl fmt::Display for TransportIoError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.direction {
+            TransportDirection::Sink => write!(fmt, "SinkError"),
+            TransportDirection::Stream => write!(fmt, "StreamError"),
+        }
+    }
+}
+
+impl Error for TransportIoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Converts into an `io::Error` of the same `ErrorKind`, whose inner error is
+/// a `TransportIoError` tagged with the originating `TransportDirection`.
+///
+/// A `ValidationError` becomes an `io::Error` of kind `InvalidData`.
+impl<SinkErr, StreamErr> From<TransportError<SinkErr, StreamErr>> for io::Error
+    where SinkErr: Into<io::Error>,
+          StreamErr: Into<io::Error>
+{
+    fn from(err: TransportError<SinkErr, StreamErr>) -> io::Error {
+        let (direction, error) = match err {
+            TransportError::SinkError(e) => (TransportDirection::Sink, e.into()),
+            TransportError::StreamError(e) => (TransportDirection::Stream, e.into()),
+            TransportError::ValidationError(e) => {
+                return io::Error::new(io::ErrorKind::InvalidData, e)
+            }
+        };
+
+        io::Error::new(error.kind(), TransportIoError { direction, error })
+    }
+}
+
+impl TransportError<io::Error, io::Error> {
+    /// Create a `TransportError` from an `io::Error` of a transport adapter,
+    /// originating from the given half of the transport.
+    pub fn from_io(direction: TransportDirection, err: io::Error) -> Self {
+        match direction {
+            TransportDirection::Sink => TransportError::SinkError(err),
+            TransportDirection::Stream => TransportError::StreamError(err),
+        }
+    }
+
+    /// Recover the `TransportError` from an `io::Error` that was created by
+    /// converting a `TransportError::SinkError` or
+    /// `TransportError::StreamError`.
+    ///
+    /// Returns the `io::Error` unchanged if it carries no `TransportIoError`.
+    ///
+    /// ```
+    /// use std::io;
+    /// use dialogue::{TransportError, TransportDirection};
+    ///
+    /// let err = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+    /// let io_err: io::Error = TransportError::from_io(TransportDirection::Stream, err).into();
+    /// assert_eq!(io_err.kind(), io::ErrorKind::ConnectionReset);
+    ///
+    /// match TransportError::try_from_io(io_err) {
+    ///     Ok(TransportError::StreamError(e)) => {
+    ///         assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+    ///         assert_eq!(e.to_string(), "reset");
+    ///     }
+    ///     _ => panic!("expected a StreamError"),
+    /// }
+    /// ```
+    pub fn try_from_io(err: io::Error) -> Result<Self, io::Error> {
+        let tagged = matches!(err.get_ref(), Some(inner) if inner.is::<TransportIoError>());
+        if !tagged {
+            return Err(err);
+        }
+
+        let payload = err.into_inner()
+            .and_then(|inner| inner.downcast::<TransportIoError>().ok())
+            .expect("checked that the io::Error carries a TransportIoError");
+        let TransportIoError { direction, error } = *payload;
+        Ok(TransportError::from_io(direction, error))
+    }
+}
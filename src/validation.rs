@@ -0,0 +1,123 @@
+use std::fmt;
+use std::error::Error;
+use std::marker::PhantomData;
+
+use futures::{Sink, Stream, Poll};
+
+use packet::{PacketWritable, PacketReadable, PacketId, PacketType};
+use dialogue::{Dialogue, Role};
+use transport_error::TransportError;
+
+/// Decides whether an incoming packet is acceptable, used by a
+/// `ValidatedDialogue`.
+pub trait PacketValidator<P> {
+    /// Returns an error if the packet should be rejected.
+    fn validate(&self, packet: &P) -> Result<(), ValidationError>;
+}
+
+/// A `PacketValidator` which rejects packets that carry no data although their
+/// `PacketType` requires it (see `PacketType::must_carry_data`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemaValidator;
+
+impl<P: PacketReadable> PacketValidator<P> for SchemaValidator {
+    fn validate(&self, packet: &P) -> Result<(), ValidationError> {
+        let packet_type = packet.get_type();
+
+        if packet_type.must_carry_data() && packet.is_empty() {
+            Err(ValidationError {
+                    id: packet.get_id(),
+                    packet_type,
+                    reason: "packet carries no data".to_string(),
+                })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An error indicating that an incoming packet was rejected by a
+/// `PacketValidator`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    /// The `PacketId` of the rejected packet.
+    pub id: PacketId,
+    /// The `PacketType` of the rejected packet.
+    pub packet_type: PacketType,
+    /// Why the packet was rejected.
+    pub reason: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt,
+               "ValidationError: {:?} packet {}: {}",
+               self.packet_type,
+               self.id,
+               self.reason)
+    }
+}
+
+impl Error for ValidationError {}
+
+/// A `Dialogue` which validates each incoming packet before routing it,
+/// created via `Dialogue::with_packet_validation`.
+///
+/// Every packet is validated, including responses and the packets of
+/// duplexes, no matter whether they are read by the `Stream` implementation
+/// or by a `Response` or `SubDuplex`. A rejected packet is dropped, and a
+/// `TransportError::ValidationError` is emitted by the `Stream`
+/// implementation, which may be polled again afterwards. For a rejected
+/// `DuplexInitial` packet, this side's half of the duplex is ended right away,
+/// so that the peer does not wait for it. Other rejected packets are treated
+/// as if they never arrived: A `Response` or `SubDuplex` they were meant for
+/// keeps waiting.
+pub struct ValidatedDialogue<P, T, SinkErr, StreamErr, Data, R, V> {
+    dialogue: Dialogue<P, T, SinkErr, StreamErr, Data, R>,
+    // The validator itself is stored in the `Dialogue`, so that the handles
+    // reading packets through it use it as well.
+    validator_type: PhantomData<fn() -> V>,
+}
+
+impl<P, T, SinkErr, StreamErr, Data, R, V> ValidatedDialogue<P, T, SinkErr, StreamErr, Data, R, V> {
+    pub(crate) fn new(dialogue: Dialogue<P, T, SinkErr, StreamErr, Data, R>)
+                      -> ValidatedDialogue<P, T, SinkErr, StreamErr, Data, R, V> {
+        ValidatedDialogue {
+            dialogue,
+            validator_type: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the wrapped `Dialogue`.
+    pub fn get_ref(&self) -> &Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        &self.dialogue
+    }
+
+    /// Returns a mutable reference to the wrapped `Dialogue`, e.g. for sending
+    /// packets or handling emitted requests and duplexes.
+    pub fn get_mut(&mut self) -> &mut Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        &mut self.dialogue
+    }
+
+    /// Consumes the `ValidatedDialogue`, returning the wrapped `Dialogue`,
+    /// which no longer validates incoming packets.
+    pub fn into_inner(mut self) -> Dialogue<P, T, SinkErr, StreamErr, Data, R> {
+        self.dialogue.remove_validator();
+        self.dialogue
+    }
+}
+
+impl<P, T, SinkErr, StreamErr, Data, R, V> Stream
+    for ValidatedDialogue<P, T, SinkErr, StreamErr, Data, R, V>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          V: PacketValidator<P>
+{
+    type Item = P;
+    type Error = TransportError<SinkErr, StreamErr>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.dialogue.poll()
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+use std::mem;
+
+use futures::{Sink, Stream, Async, AsyncSink};
+
+use packet::{PacketWritable, PacketReadable};
+use dialogue::{SubDuplex, Role};
+
+/// An `io::Write` adapter for a `SubDuplex`, created via `SubDuplex::as_write`.
+///
+/// Written bytes are buffered, and `flush` sends all buffered bytes as a
+/// single item on the duplex and flushes the transport. If the transport is
+/// not ready, `flush` fails with `io::ErrorKind::WouldBlock` and has to be
+/// called again later. Bytes written in the meantime are sent as a separate
+/// item by that later `flush`. Like the `Sink` implementation of the `SubDuplex`, this
+/// must be used from within a task.
+///
+/// Bytes which have not been flushed are lost when the writer is dropped.
+pub struct SubDuplexWriter<'a,
+                           'ps: 'a,
+                           P: 'ps,
+                           T: 'ps,
+                           SinkErr: 'ps,
+                           StreamErr: 'ps,
+                           Data: 'ps,
+                           R: 'ps,
+                           SubDuplexType: 'static>
+{
+    duplex: &'a mut SubDuplex<'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>,
+    buffer: Vec<u8>,
+    pending: Option<Data>,
+}
+
+impl<'a,
+     'ps: 'a,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static>
+    SubDuplexWriter<'a, 'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType> {
+    pub(crate) fn new(duplex: &'a mut SubDuplex<'ps,
+                                                P,
+                                                T,
+                                                SinkErr,
+                                                StreamErr,
+                                                Data,
+                                                R,
+                                                SubDuplexType>)
+                      -> Self {
+        SubDuplexWriter {
+            duplex,
+            buffer: Vec::new(),
+            pending: None,
+        }
+    }
+}
+
+impl<'a,
+     'ps: 'a,
+     P: 'ps,
+     T: 'ps,
+     SinkErr: 'ps,
+     StreamErr: 'ps,
+     Data: 'ps,
+     R: 'ps,
+     SubDuplexType: 'static> io::Write
+    for SubDuplexWriter<'a, 'ps, P, T, SinkErr, StreamErr, Data, R, SubDuplexType>
+    where P: PacketReadable<Data = Data> + PacketWritable<Data = Data>,
+          T: Sink<SinkItem = P, SinkError = SinkErr> + Stream<Item = P, Error = StreamErr>,
+          R: Role,
+          Data: From<Vec<u8>>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Bytes written after a `WouldBlock` are sent as another item once the
+        // pending one has been accepted.
+        loop {
+            let data = match self.pending.take() {
+                Some(data) => data,
+                None if self.buffer.is_empty() => break,
+                None => Data::from(mem::take(&mut self.buffer)),
+            };
+
+            match self.duplex.start_send(data) {
+                Ok(AsyncSink::Ready) => {}
+                Ok(AsyncSink::NotReady(data)) => {
+                    self.pending = Some(data);
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                Err(err) => return Err(io::Error::new(io::ErrorKind::BrokenPipe, err)),
+            }
+        }
+
+        match self.duplex.poll_complete() {
+            Ok(Async::Ready(())) => Ok(()),
+            Ok(Async::NotReady) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(err) => Err(io::Error::new(io::ErrorKind::BrokenPipe, err)),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Async, AsyncSink, Sink, Stream};
+
+use dialogue::{Dialogue, PacketReadable, PacketType, SubStreamError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once, bounded_transports, BoundedDialogue};
+
+#[test]
+fn crossing_close_messages() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    // The server asks the client to close, and keeps operating normally.
+    assert_eq!(poll_once(|| server.close()).unwrap(), Async::NotReady);
+    server.message(b"late".to_vec()).unwrap();
+    run(|| server.poll_complete()).unwrap();
+
+    // The client's close message crosses the server's request to close, which
+    // must not be mistaken for the server's answer.
+    assert_eq!(poll_once(|| client.close()).unwrap(), Async::NotReady);
+    assert!(client.is_close_requested());
+
+    run(|| server.close()).unwrap();
+    run(|| client.close()).unwrap();
+
+    // The message sent after the request to close still arrives.
+    let packet = run(|| client.poll()).unwrap().unwrap();
+    assert_eq!(packet.get_type(), PacketType::Message);
+    assert_eq!(packet.get_data(), Some(b"late".to_vec()));
+    assert_eq!(run(|| client.poll()).unwrap(), None);
+    assert!(!client.is_aborted_by_peer());
+}
+
+#[test]
+fn server_answers_close_once_routes_are_done() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    {
+        let mut duplex = client.sub_duplex(vec![0]).unwrap();
+        run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    }
+    assert_eq!(poll_once(|| client.close()).unwrap(), Async::NotReady);
+
+    // The client's duplex has not been emitted by the server yet, so the server
+    // does not answer.
+    assert_eq!(poll_once(|| server.close()).unwrap(), Async::NotReady);
+    assert_eq!(poll_once(|| client.close()).unwrap(), Async::NotReady);
+
+    // Emitting the duplex without accepting it discards it.
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(initial.get_type(), PacketType::DuplexInitial);
+    assert_eq!(run(|| server.poll()).unwrap(), None);
+
+    run(|| server.close()).unwrap();
+    run(|| client.close()).unwrap();
+}
+
+#[test]
+fn abort_is_signalled_to_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let initial = {
+        let mut duplex = server.sub_duplex(vec![0]).unwrap();
+        run(|| Sink::poll_complete(&mut duplex)).unwrap();
+        let initial = run(|| client.poll()).unwrap().unwrap();
+
+        run(|| client.abort()).unwrap();
+
+        assert_eq!(run(|| duplex.poll()), Err(SubStreamError::ClosedDialogue));
+        initial
+    };
+
+    assert_eq!(run(|| server.poll()).unwrap(), None);
+    assert!(server.is_aborted_by_peer());
+    assert!(!client.is_aborted_by_peer());
+    run(|| server.close()).unwrap();
+
+    let mut late = client.packet_as_sub_duplex(initial);
+    assert_eq!(poll_once(|| late.poll()), Err(SubStreamError::ClosedDialogue));
+}
+
+#[test]
+fn queued_packets_are_flushed_before_the_close_message() {
+    let (a, mut b) = bounded_transports(0);
+    let mut client: BoundedDialogue<_> = Dialogue::client(a);
+
+    {
+        let mut duplex = client.sub_duplex(vec![0]).unwrap();
+        // Send items until neither the channel nor the queue have room left.
+        for i in 1.. {
+            match poll_once(|| duplex.start_send(vec![i]).map(Async::Ready)).unwrap() {
+                Async::Ready(AsyncSink::Ready) => {}
+                _ => break,
+            }
+        }
+    }
+    // Dropping the duplex queues its end as well.
+    assert!(client.needs_flush());
+
+    let mut received = Vec::new();
+    loop {
+        assert!(poll_once(|| client.close()).unwrap().is_not_ready());
+        let packet = run(|| b.poll()).unwrap().unwrap();
+        if packet.get_type() == PacketType::Message {
+            assert_eq!(packet.get_id(), 0);
+            assert_eq!(packet.get_data(), None);
+            break;
+        }
+        received.push((packet.get_type(), packet.get_data()));
+    }
+    assert_eq!(received,
+               vec![(PacketType::DuplexInitial, Some(vec![0])),
+                    (PacketType::DuplexRequest, Some(vec![1])),
+                    (PacketType::DuplexRequestEnd, None)]);
+}
@@ -0,0 +1,111 @@
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::{Future, Poll, Sink, StartSend, Stream};
+use futures::executor::{self, Notify, NotifyHandle};
+use futures::future;
+use futures::sync::mpsc::{self, Sender, Receiver, SendError};
+
+use dialogue::{Dialogue, PacketWritable, PacketId, PacketType};
+use dialogue::testing::ConcretePacket;
+
+/// Polls `f` until it is ready, blocking the current thread.
+pub fn run<F, T, E>(f: F) -> Result<T, E>
+    where F: FnMut() -> Poll<T, E>
+{
+    future::poll_fn(f).wait()
+}
+
+/// Polls `f` once, in a task whose notifications are counted by `counter`.
+pub fn poll_counted<F, T, E>(counter: &Arc<Counter>, mut f: F) -> Poll<T, E>
+    where F: FnMut() -> Poll<T, E>
+{
+    let notify = NotifyHandle::from(counter.clone());
+    executor::spawn(future::poll_fn(&mut f)).poll_future_notify(&notify, 0)
+}
+
+/// Polls `f` once, in a task whose notifications are ignored.
+pub fn poll_once<F, T, E>(f: F) -> Poll<T, E>
+    where F: FnMut() -> Poll<T, E>
+{
+    poll_counted(&Arc::new(Counter::default()), f)
+}
+
+/// Counts task notifications.
+#[derive(Default)]
+pub struct Counter(AtomicUsize);
+
+impl Counter {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Notify for Counter {
+    fn notify(&self, _id: usize) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Creates a packet, for sending it via a raw transport.
+pub fn packet(data: Option<&[u8]>, id: PacketId, packet_type: PacketType) -> ConcretePacket {
+    let mut packet = ConcretePacket::new(data.map(|data| data.to_vec()));
+    packet.set_id(id);
+    packet.set_type(packet_type);
+    packet
+}
+
+/// A transport over bounded channels, so that a peer which stops reading
+/// applies backpressure.
+pub struct Bounded {
+    sender: Sender<ConcretePacket>,
+    receiver: Receiver<ConcretePacket>,
+}
+
+/// Creates two connected transports whose channels hold up to `buffer`
+/// packets (plus one per sender).
+pub fn bounded_transports(buffer: usize) -> (Bounded, Bounded) {
+    let (a_sender, b_receiver) = mpsc::channel(buffer);
+    let (b_sender, a_receiver) = mpsc::channel(buffer);
+
+    (Bounded {
+         sender: a_sender,
+         receiver: a_receiver,
+     },
+     Bounded {
+         sender: b_sender,
+         receiver: b_receiver,
+     })
+}
+
+impl Sink for Bounded {
+    type SinkItem = ConcretePacket;
+    type SinkError = SendError<ConcretePacket>;
+
+    fn start_send(&mut self, item: ConcretePacket) -> StartSend<ConcretePacket, Self::SinkError> {
+        self.sender.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.sender.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.sender.close()
+    }
+}
+
+impl Stream for Bounded {
+    type Item = ConcretePacket;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<ConcretePacket>, ()> {
+        self.receiver.poll()
+    }
+}
+
+/// A `Dialogue` over a `Bounded` transport.
+pub type BoundedDialogue<R> =
+    Dialogue<ConcretePacket, Bounded, SendError<ConcretePacket>, (), Vec<u8>, R>;
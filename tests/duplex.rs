@@ -0,0 +1,100 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::mem;
+use std::sync::Arc;
+
+use futures::{Sink, Stream};
+
+use dialogue::{Dialogue, SubStreamError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once};
+
+#[test]
+fn close_waits_for_the_end_of_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    // Only this half is closed, the peer may still send items.
+    assert!(poll_once(|| Sink::close(&mut duplex)).unwrap().is_not_ready());
+    assert_eq!(run(|| accepted.poll()), Ok(None));
+    accepted.start_send(vec![1]).unwrap();
+    run(|| Sink::close(&mut accepted)).unwrap();
+
+    assert_eq!(run(|| duplex.poll()), Ok(Some(vec![1])));
+    run(|| Sink::close(&mut duplex)).unwrap();
+    assert_eq!(run(|| duplex.poll()), Ok(None));
+
+    drop(duplex);
+    assert_eq!(client.active_ids(), 0);
+}
+
+#[test]
+fn close_error_is_received_by_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    assert!(poll_once(|| duplex.close_error(vec![7])).unwrap().is_not_ready());
+    assert_eq!(run(|| accepted.poll()),
+               Err(SubStreamError::EndWithError(Arc::new(vec![7]))));
+    run(|| Sink::close(&mut accepted)).unwrap();
+
+    run(|| duplex.close_error(vec![7])).unwrap();
+    drop(duplex);
+    assert_eq!(client.active_ids(), 0);
+}
+
+#[test]
+fn abort_does_not_wait_for_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    run(|| duplex.abort()).unwrap();
+    // Aborting releases the id right away, not only once the duplex is dropped.
+    mem::forget(duplex);
+    assert_eq!(client.active_ids(), 0);
+
+    assert_eq!(run(|| accepted.poll()), Ok(None));
+}
+
+#[test]
+fn abort_error_is_received_by_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    run(|| duplex.abort_error(vec![7])).unwrap();
+    // An aborted duplex does not receive any more items.
+    assert_eq!(run(|| duplex.poll()), Ok(None));
+    mem::forget(duplex);
+    assert_eq!(client.active_ids(), 0);
+
+    assert_eq!(run(|| accepted.poll()),
+               Err(SubStreamError::EndWithError(Arc::new(vec![7]))));
+}
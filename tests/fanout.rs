@@ -0,0 +1,38 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Future, Sink};
+
+use dialogue::{Dialogue, FanoutError, PacketType};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, packet};
+
+#[test]
+fn failing_peers_decline_by_default() {
+    let (a, mut raw) = paired_transports();
+    let (b, closed) = paired_transports();
+    drop(closed);
+    let mut peers: Vec<TestDialogue<_>> = vec![Dialogue::client(a), Dialogue::client(b)];
+
+    raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+    let responses = run(|| Dialogue::fanout_request(vec![0], &mut peers).poll());
+
+    assert_eq!(responses, Ok(vec![Some(vec![1]), None]));
+}
+
+#[test]
+fn propagated_errors_report_the_failing_peer() {
+    let (a, raw) = paired_transports();
+    let (b, closed) = paired_transports();
+    drop(closed);
+    let mut peers: Vec<TestDialogue<_>> = vec![Dialogue::client(a), Dialogue::client(b)];
+
+    let err = Dialogue::fanout_request(vec![0], &mut peers).propagate_errors().wait();
+
+    assert_eq!(err, Err(FanoutError::ClosedDialogue { index: 1 }));
+    assert_eq!(err.unwrap_err().index(), 1);
+    drop(raw);
+}
@@ -0,0 +1,57 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Future, Sink, Stream};
+
+use dialogue::{Dialogue, PacketReadable, PacketType};
+use dialogue::testing::{paired_transports, ConcretePacket, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+#[test]
+fn filtered_duplex_is_ended_and_its_route_removed() {
+    let (mut raw, b) = paired_transports();
+    let server: TestDialogue<_> = Dialogue::server(b);
+    let mut server = server.recv_filter(|packet: &ConcretePacket| {
+                                            packet.get_type() != PacketType::DuplexInitial
+                                        });
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    raw.start_send(packet(Some(&[1]), 1, PacketType::DuplexRequest)).unwrap();
+    raw.start_send(packet(Some(&[7]), 3, PacketType::Message)).unwrap();
+
+    let next = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(next.get_type(), PacketType::Message);
+    assert_eq!(next.get_data(), Some(vec![7]));
+
+    // The peer is told right away that the duplex has ended.
+    run(|| server.get_mut().poll_complete()).unwrap();
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(None, 1, PacketType::DuplexResponseEnd)));
+
+    // Without a route, the id may be used for a new duplex.
+    raw.start_send(packet(Some(&[2]), 1, PacketType::DuplexInitial)).unwrap();
+    let initial = run(|| server.get_mut().poll()).unwrap().unwrap();
+    assert_eq!(initial.get_data(), Some(vec![2]));
+}
+
+#[test]
+fn filtered_request_is_declined() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let server: TestDialogue<_> = Dialogue::server(b);
+    let mut server = server.recv_filter(|packet: &ConcretePacket| {
+                                            packet.get_type() != PacketType::Request
+                                        });
+
+    let mut response = client.request(vec![0]).unwrap();
+    run(|| response.poll_complete()).unwrap();
+
+    assert!(poll_once(|| server.poll()).unwrap().is_not_ready());
+    run(|| server.get_mut().poll_complete()).unwrap();
+
+    // The peer does not wait for an answer that never comes.
+    assert_eq!(run(|| response.poll()).unwrap(), None);
+}
@@ -0,0 +1,108 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::sync::Arc;
+
+use futures::{Future, Sink, Stream};
+
+use dialogue::{Dialogue, SubStreamError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once};
+
+// The fold function is passed a `&Data`, which is a `&Vec<u8>` here.
+#[allow(clippy::ptr_arg)]
+fn append(mut acc: Vec<u8>, item: &Vec<u8>) -> Vec<u8> {
+    acc.extend_from_slice(item);
+    acc
+}
+
+#[test]
+fn accumulated_value_is_sent_on_close() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut sink = client.sub_fold_sink(vec![0], append, vec![]).unwrap();
+    sink.start_send(vec![1, 2]).unwrap();
+    sink.start_send(vec![3]).unwrap();
+    assert_eq!(sink.get_acc(), Some(&vec![0, 1, 2, 3]));
+    assert!(poll_once(|| sink.close()).unwrap().is_not_ready());
+    assert_eq!(sink.get_acc(), None);
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let total: Option<Vec<u8>> = server.packet_as_sub_fold_stream(initial).wait().unwrap();
+    assert_eq!(total, Some(vec![0, 1, 2, 3]));
+
+    run(|| sink.close()).unwrap();
+    assert!(sink.start_send(vec![4]).is_err());
+}
+
+#[test]
+fn initial_value_is_sent_without_items() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut sink = client.sub_fold_sink(vec![0], append, vec![]).unwrap();
+    assert!(poll_once(|| sink.close()).unwrap().is_not_ready());
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let total: Option<Vec<u8>> = server.packet_as_sub_fold_stream(initial).wait().unwrap();
+    assert_eq!(total, Some(vec![0]));
+}
+
+#[test]
+fn regular_end_without_items_resolves_to_none() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    {
+        let mut duplex = client.sub_duplex(vec![]).unwrap();
+        run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let total: Option<Vec<u8>> = server.packet_as_sub_fold_stream(initial).wait().unwrap();
+    assert_eq!(total, None);
+}
+
+#[test]
+fn abort_error_is_received_by_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut sink = client.sub_fold_sink(vec![0], append, vec![]).unwrap();
+    sink.start_send(vec![1]).unwrap();
+    run(|| sink.abort_error(vec![7])).unwrap();
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let stream = server.packet_as_sub_fold_stream::<Vec<u8>>(initial);
+    assert_eq!(stream.wait(),
+               Err(SubStreamError::EndWithError(Arc::new(vec![7]))));
+}
+
+#[test]
+fn dropping_the_sink_is_an_error_for_the_peer() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    {
+        let mut sink = client.sub_fold_sink(vec![0], append, vec![]).unwrap();
+        sink.start_send(vec![1]).unwrap();
+        sink.start_send(vec![2]).unwrap();
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    // The last item is not mistaken for the accumulated value.
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let stream = server.packet_as_sub_fold_stream::<Vec<u8>>(initial);
+    assert_eq!(stream.wait(),
+               Err(SubStreamError::EndWithError(Arc::new(vec![0, 1, 2]))));
+}
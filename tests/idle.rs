@@ -0,0 +1,47 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::sync::Arc;
+
+use futures::{Async, Stream};
+
+use dialogue::Dialogue;
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{poll_counted, Counter};
+
+// One poll per second of an hour. A `Dialogue` has no timers, so the number
+// of polls only stands in for the time an idle dialogue is kept around.
+const POLLS: usize = 60 * 60;
+
+#[test]
+fn idle_dialogues_are_never_woken() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+    let client_wakeups = Arc::new(Counter::default());
+    let server_wakeups = Arc::new(Counter::default());
+
+    for _ in 0..POLLS {
+        assert_eq!(poll_counted(&client_wakeups, || client.poll()).unwrap(),
+                   Async::NotReady);
+        assert_eq!(poll_counted(&client_wakeups, || client.poll_complete()).unwrap(),
+                   Async::Ready(()));
+        assert_eq!(poll_counted(&server_wakeups, || server.poll()).unwrap(),
+                   Async::NotReady);
+        assert_eq!(poll_counted(&server_wakeups, || server.poll_complete()).unwrap(),
+                   Async::Ready(()));
+    }
+
+    // There are no timers, so neither side woke itself or the other side.
+    assert_eq!(client_wakeups.get(), 0);
+    assert_eq!(server_wakeups.get(), 0);
+
+    // The task is registered with the transport nonetheless.
+    client.message(vec![0]).unwrap();
+    poll_counted(&client_wakeups, || client.poll_complete()).unwrap();
+    assert_eq!(server_wakeups.get(), 1);
+    assert_eq!(client_wakeups.get(), 0);
+}
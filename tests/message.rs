@@ -0,0 +1,76 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::collections::HashSet;
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+
+use dialogue::{Dialogue, MessageError, PacketReadable, PacketType, TransportError};
+use dialogue::testing::{paired_transports, ConcretePacket, TestDialogue};
+
+use common::run;
+
+#[test]
+fn messages_arrive_in_order_with_distinct_ids() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    for i in 0..10u8 {
+        assert_eq!(client.message(vec![i]), Ok(AsyncSink::Ready));
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    let mut ids = HashSet::new();
+    for i in 0..10u8 {
+        let packet = run(|| server.poll()).unwrap().unwrap();
+        assert_eq!(packet.get_type(), PacketType::Message);
+        assert_eq!(packet.get_data(), Some(vec![i]));
+        assert!(ids.insert(packet.get_id()));
+    }
+}
+
+/// A transport whose sink always fails, and whose stream never yields.
+struct BrokenSink;
+
+impl Sink for BrokenSink {
+    type SinkItem = ConcretePacket;
+    type SinkError = &'static str;
+
+    fn start_send(&mut self, _item: ConcretePacket) -> StartSend<ConcretePacket, &'static str> {
+        Err("broken")
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), &'static str> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Stream for BrokenSink {
+    type Item = ConcretePacket;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<ConcretePacket>, ()> {
+        Ok(Async::NotReady)
+    }
+}
+
+#[test]
+fn transport_error_closes_the_dialogue() {
+    let mut client: Dialogue<ConcretePacket, BrokenSink, &'static str, (), Vec<u8>, _> =
+        Dialogue::client(BrokenSink);
+
+    assert_eq!(client.message(vec![0]), Err(MessageError::ClosedDialogue));
+    // Later calls fail consistently, without using the transport again.
+    assert_eq!(client.message(vec![1]), Err(MessageError::ClosedDialogue));
+    assert!(Future::wait(client.request(vec![2]).unwrap()).is_err());
+
+    // The error is emitted once, then the stream ends.
+    match run(|| client.poll()) {
+        Err(TransportError::SinkError("broken")) => {}
+        other => panic!("expected the sink error, got {:?}", other),
+    }
+    assert_eq!(run(|| client.poll()).unwrap(), None);
+}
@@ -0,0 +1,196 @@
+//! The error paths of duplexes with a `Data` type which does not implement
+//! `Clone`.
+
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use futures::{Future, Sink, Stream};
+
+use dialogue::{Dialogue, PacketId, PacketReadable, PacketType, PacketWritable, SubStreamError};
+use dialogue::testing::{paired_transports, PairedTransport, Never};
+
+use common::{run, poll_once};
+
+/// Data which can not be cloned.
+#[derive(Debug, PartialEq)]
+struct Payload(u8);
+
+/// A packet which hands out its data only once, so that it does not need to
+/// clone it.
+#[derive(Debug)]
+struct NoClonePacket {
+    id: PacketId,
+    packet_type: PacketType,
+    empty: bool,
+    data: RefCell<Option<Payload>>,
+}
+
+impl PacketWritable for NoClonePacket {
+    type Data = Payload;
+
+    fn set_id(&mut self, id: PacketId) {
+        self.id = id;
+    }
+
+    fn set_type(&mut self, t: PacketType) {
+        self.packet_type = t;
+    }
+
+    fn new(data: Option<Payload>) -> NoClonePacket {
+        NoClonePacket {
+            id: 0,
+            packet_type: PacketType::Message,
+            empty: data.is_none(),
+            data: RefCell::new(data),
+        }
+    }
+}
+
+impl PacketReadable for NoClonePacket {
+    type Data = Payload;
+
+    fn get_id(&self) -> PacketId {
+        self.id
+    }
+
+    fn get_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    fn get_data(&self) -> Option<Payload> {
+        self.data.borrow_mut().take()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.empty
+    }
+}
+
+type NoCloneDialogue<R> =
+    Dialogue<NoClonePacket, PairedTransport<NoClonePacket>, Never, Never, Payload, R>;
+
+fn dialogues() -> (NoCloneDialogue<dialogue::Client>, NoCloneDialogue<dialogue::Server>) {
+    let (a, b) = paired_transports();
+    (Dialogue::client(a), Dialogue::server(b))
+}
+
+#[test]
+fn end_with_error_shares_the_data() {
+    let (mut client, mut server) = dialogues();
+
+    let mut duplex = client.sub_duplex(Payload(0)).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    assert!(poll_once(|| duplex.close_error(Payload(7))).unwrap().is_not_ready());
+
+    let err = run(|| accepted.poll()).unwrap_err();
+    let copy = err.clone();
+    match (err, copy) {
+        (SubStreamError::EndWithError(data), SubStreamError::EndWithError(copy)) => {
+            assert_eq!(*data, Payload(7));
+            assert!(Arc::ptr_eq(&data, &copy));
+        }
+        _ => panic!("expected EndWithError"),
+    }
+}
+
+#[test]
+fn on_close_receives_the_error_data() {
+    let (mut client, mut server) = dialogues();
+
+    let mut duplex = client.sub_duplex(Payload(0)).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+
+    let mut closed = None;
+    {
+        let mut accepted = server
+            .packet_as_sub_duplex(initial)
+            .on_close(|data| closed = Some(data));
+
+        assert!(poll_once(|| duplex.close_error(Payload(7))).unwrap().is_not_ready());
+
+        match run(|| accepted.poll()) {
+            Err(SubStreamError::EndWithError(data)) => assert_eq!(*data, Payload(7)),
+            other => panic!("expected EndWithError, got {:?}", other),
+        }
+    }
+
+    let data = closed.unwrap().unwrap();
+    assert_eq!(*data, Payload(7));
+}
+
+#[test]
+fn chunks_forward_the_error_data() {
+    let (mut client, mut server) = dialogues();
+
+    let mut duplex = client.sub_duplex(Payload(0)).unwrap();
+    duplex.start_send(Payload(1)).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut chunks = server.packet_as_sub_duplex(initial).chunks(2);
+
+    assert!(poll_once(|| duplex.close_error(Payload(7))).unwrap().is_not_ready());
+
+    assert_eq!(run(|| chunks.poll()), Ok(Some(vec![Payload(1)])));
+    match run(|| chunks.poll()) {
+        Err(SubStreamError::EndWithError(data)) => assert_eq!(*data, Payload(7)),
+        other => panic!("expected EndWithError, got {:?}", other),
+    }
+}
+
+#[test]
+fn expect_exactly_reports_too_few_items() {
+    let (mut client, mut server) = dialogues();
+
+    let mut duplex = client.sub_duplex(Payload(0)).unwrap();
+    duplex.start_send(Payload(1)).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let expected = server.packet_as_sub_duplex(initial).expect_exactly(2);
+
+    assert!(poll_once(|| Sink::close(&mut duplex)).unwrap().is_not_ready());
+
+    assert_eq!(expected.wait(),
+               Err(SubStreamError::TooFewItems {
+                       received: 1,
+                       expected: 2,
+                   }));
+}
+
+#[test]
+fn reduce_sink_reports_the_error_data() {
+    let (mut client, mut server) = dialogues();
+
+    let mut sink = client.sub_reduce_sink(Payload(0)).unwrap();
+    run(|| Sink::poll_complete(&mut sink)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut stream = server.packet_as_sub_reduce_stream(initial);
+
+    run(|| stream.abort_error(Payload(7))).unwrap();
+
+    match Future::wait(sink) {
+        Err(SubStreamError::EndWithError(data)) => assert_eq!(*data, Payload(7)),
+        other => panic!("expected EndWithError, got {:?}", other),
+    }
+}
+
+#[test]
+fn closed_dialogue_needs_no_data() {
+    let (mut client, mut server) = dialogues();
+
+    let mut duplex = server.sub_duplex(Payload(0)).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    run(|| client.poll()).unwrap().unwrap();
+    run(|| client.abort()).unwrap();
+
+    let err = run(|| duplex.poll()).unwrap_err();
+    assert_eq!(err.clone(), SubStreamError::ClosedDialogue);
+}
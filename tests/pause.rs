@@ -0,0 +1,60 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::sync::Arc;
+
+use futures::{Async, AsyncSink, Stream};
+
+use dialogue::{Dialogue, PacketReadable, PacketType};
+
+use common::{run, poll_counted, poll_once, Counter, bounded_transports, BoundedDialogue};
+
+const MESSAGES: u8 = 20;
+
+/// Starts sending the message with the given number, within a task.
+fn send(client: &mut BoundedDialogue<dialogue::Client>, i: u8) -> bool {
+    let result = poll_once(|| Ok::<_, ()>(Async::Ready(client.message(vec![i]).unwrap())));
+    matches!(result, Ok(Async::Ready(AsyncSink::Ready)))
+}
+
+#[test]
+fn paused_reading_backs_up_and_resumes_in_order() {
+    let (a, b) = bounded_transports(2);
+    let mut client: BoundedDialogue<_> = Dialogue::client(a);
+    let mut server: BoundedDialogue<_> = Dialogue::server(b);
+
+    server.pause_reading();
+    let counter = Arc::new(Counter::default());
+    assert_eq!(poll_counted(&counter, || server.poll()).unwrap(), Async::NotReady);
+
+    // The paused server does not read, so the channel fills up.
+    let mut sent = 0;
+    while sent < MESSAGES && send(&mut client, sent) {
+        sent += 1;
+    }
+    assert!(sent < MESSAGES);
+    assert_eq!(poll_counted(&counter, || server.poll()).unwrap(), Async::NotReady);
+    assert_eq!(counter.get(), 0);
+
+    server.resume_reading();
+    assert_eq!(counter.get(), 1);
+    assert!(!server.is_reading_paused());
+
+    // All messages arrive in order, including those sent after resuming.
+    let mut received = 0;
+    while received < MESSAGES {
+        if sent < MESSAGES && send(&mut client, sent) {
+            sent += 1;
+            continue;
+        }
+
+        let packet = run(|| server.poll()).unwrap().unwrap();
+        assert_eq!(packet.get_type(), PacketType::Message);
+        assert_eq!(packet.get_data(), Some(vec![received]));
+        received += 1;
+    }
+    assert_eq!(sent, MESSAGES);
+    assert_eq!(poll_once(|| server.poll()).unwrap(), Async::NotReady);
+}
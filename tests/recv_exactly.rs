@@ -0,0 +1,74 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Future, Sink, Stream};
+
+use dialogue::{Dialogue, PacketReadable, PacketType, SubStreamError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+#[test]
+fn received_duplexes_stay_open() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    raw.start_send(packet(Some(&[1]), 3, PacketType::DuplexInitial)).unwrap();
+    raw.start_send(packet(Some(&[2]), 1, PacketType::DuplexRequest)).unwrap();
+    raw.start_send(packet(Some(&[3]), 3, PacketType::DuplexRequest)).unwrap();
+
+    let mut packets = server.recv_exactly(2).wait().unwrap();
+    assert_eq!(packets.len(), 2);
+    assert!(packets.iter().all(|packet| packet.get_type() == PacketType::DuplexInitial));
+
+    // Neither duplex has been ended towards the peer.
+    run(|| server.poll_complete()).unwrap();
+    assert!(poll_once(|| raw.poll()).unwrap().is_not_ready());
+
+    let second = packets.pop().unwrap();
+    let first = packets.pop().unwrap();
+    {
+        let mut duplex = server.packet_as_sub_duplex(first);
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![2])));
+    }
+    {
+        let mut duplex = server.packet_as_sub_duplex(second);
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![3])));
+    }
+}
+
+#[test]
+fn strict_expect_exactly_reports_surplus_items() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    for i in 1..4 {
+        raw.start_send(packet(Some(&[i]), 1, PacketType::DuplexRequest)).unwrap();
+    }
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let expected = server.packet_as_sub_duplex(initial).expect_exactly(2).strict();
+    assert_eq!(expected.wait(), Err(SubStreamError::TooManyItems { expected: 2 }));
+}
+
+#[test]
+fn strict_expect_exactly_waits_for_the_end() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    for i in 1..3 {
+        raw.start_send(packet(Some(&[i]), 1, PacketType::DuplexRequest)).unwrap();
+    }
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut expected = server.packet_as_sub_duplex(initial).expect_exactly(2).strict();
+    assert!(poll_once(|| expected.poll()).unwrap().is_not_ready());
+
+    raw.start_send(packet(None, 1, PacketType::DuplexRequestEnd)).unwrap();
+    assert_eq!(run(|| expected.poll()), Ok(vec![vec![1], vec![2]]));
+}
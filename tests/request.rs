@@ -0,0 +1,105 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Async, Future, Sink, Stream};
+
+use dialogue::{Dialogue, RespondError, PacketType};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+#[test]
+fn responding_after_processed_cancellation_fails() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::Request)).unwrap();
+    let request = run(|| server.poll()).unwrap().unwrap();
+    let mut request = server.packet_as_request(request);
+
+    raw.start_send(packet(None, 1, PacketType::Request)).unwrap();
+    run(|| request.poll()).unwrap();
+
+    assert_eq!(request.start_responding(vec![1]).err(),
+               Some(RespondError::AlreadyCancelled));
+}
+
+#[test]
+fn cancellation_after_queued_response_is_ignored() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::Request)).unwrap();
+    let request = run(|| server.poll()).unwrap().unwrap();
+
+    // The cancellation is already buffered, but not yet processed.
+    raw.start_send(packet(None, 1, PacketType::Request)).unwrap();
+    assert!(server
+                .packet_as_request(request)
+                .start_responding(vec![1])
+                .unwrap()
+                .is_ready());
+    run(|| server.poll_complete()).unwrap();
+
+    // The late cancellation neither errors nor is emitted.
+    assert_eq!(poll_once(|| server.poll()).unwrap(), Async::NotReady);
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(Some(&[1]), 1, PacketType::Response)));
+}
+
+#[test]
+fn response_after_cancelling_is_ignored() {
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+
+    {
+        let mut response = client.request(vec![0]).unwrap();
+        run(|| response.poll_complete()).unwrap();
+        assert_eq!(run(|| raw.poll()).unwrap(),
+                   Some(packet(Some(&[0]), 1, PacketType::Request)));
+
+        // The response crosses the cancellation.
+        raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+        assert!(response.start_cancel().unwrap().is_ready());
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(None, 1, PacketType::Request)));
+    assert_eq!(poll_once(|| client.poll()).unwrap(), Async::NotReady);
+    assert_eq!(client.active_ids(), 0);
+}
+
+#[test]
+fn response_before_cancelling_is_received() {
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+
+    let mut response = client.request(vec![0]).unwrap();
+    run(|| response.poll_complete()).unwrap();
+    raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+
+    assert_eq!(run(|| response.poll()).unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn request_resolves_once_the_peer_closed() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let response = client.request(vec![0]).unwrap();
+    drop(response);
+    assert!(poll_once(|| client.close()).unwrap().is_not_ready());
+
+    let request = run(|| server.poll()).unwrap().unwrap();
+    {
+        let mut request = server.packet_as_request(request);
+        // After the client's close message, no cancellation can arrive.
+        assert_eq!(run(|| request.poll()), Ok(()));
+    }
+    assert!(server.is_closing());
+    assert!(!server.is_closed());
+}
@@ -0,0 +1,126 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Async, Future, Sink, Stream};
+
+use dialogue::{Dialogue, PacketReadable, PacketType, SubStreamError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+#[test]
+fn unaccepted_duplex_is_discarded() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    duplex.start_send(vec![1]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(initial.get_type(), PacketType::DuplexInitial);
+
+    // Polling again without accepting the duplex discards it.
+    assert_eq!(poll_once(|| server.poll()).unwrap(), Async::NotReady);
+    run(|| server.poll_complete()).unwrap();
+
+    // The peer is told that this side ended the duplex.
+    assert_eq!(run(|| duplex.poll()), Ok(None));
+
+    // Later items for the discarded duplex are not buffered.
+    duplex.start_send(vec![2]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+    assert_eq!(poll_once(|| server.poll()).unwrap(), Async::NotReady);
+
+    let mut late = server.packet_as_sub_duplex(initial);
+    assert_eq!(poll_once(|| late.poll()), Err(SubStreamError::ClosedDialogue));
+}
+
+#[test]
+fn peeked_duplex_is_not_discarded() {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    duplex.start_send(vec![1]).unwrap();
+    run(|| Sink::poll_complete(&mut duplex)).unwrap();
+
+    assert_eq!(run(|| server.peek_event()).unwrap().unwrap().0,
+               PacketType::DuplexInitial);
+    let initial = run(|| server.poll()).unwrap().unwrap();
+
+    let mut accepted = server.packet_as_sub_duplex(initial);
+    assert_eq!(run(|| accepted.poll()), Ok(Some(vec![1])));
+}
+
+#[test]
+fn duplicate_duplex_id_is_rejected() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+
+    {
+        let mut duplex = server.packet_as_sub_duplex(initial);
+
+        raw.start_send(packet(Some(&[9]), 1, PacketType::DuplexInitial)).unwrap();
+        raw.start_send(packet(Some(&[1]), 1, PacketType::DuplexRequest)).unwrap();
+        raw.start_send(packet(Some(&[7]), 3, PacketType::Message)).unwrap();
+
+        // The duplicate neither replaces the live duplex nor loses its items.
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![1])));
+    }
+
+    // The duplicate is not emitted as a fresh packet either.
+    let next = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(next.get_type(), PacketType::Message);
+    assert_eq!(next.get_data(), Some(vec![7]));
+}
+
+#[test]
+fn duplicate_request_id_is_rejected() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::Request)).unwrap();
+    let request = run(|| server.poll()).unwrap().unwrap();
+
+    {
+        let mut request = server.packet_as_request(request);
+        raw.start_send(packet(Some(&[9]), 1, PacketType::Request)).unwrap();
+        raw.start_send(packet(Some(&[7]), 3, PacketType::Message)).unwrap();
+
+        // Waiting for cancellation routes both packets.
+        assert_eq!(poll_once(|| request.poll()), Ok(Async::NotReady));
+    }
+
+    let next = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(next.get_type(), PacketType::Message);
+}
+
+#[test]
+fn items_arriving_before_acceptance_are_buffered() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    for i in 1..6 {
+        raw.start_send(packet(Some(&[i]), 1, PacketType::DuplexRequest)).unwrap();
+    }
+    raw.start_send(packet(Some(&[7]), 3, PacketType::Message)).unwrap();
+
+    // Receiving the message routes all items before the duplex is accepted.
+    let mut packets = server.recv_exactly(2).wait().unwrap();
+    assert_eq!(packets[1].get_type(), PacketType::Message);
+
+    let mut duplex = server.packet_as_sub_duplex(packets.remove(0));
+    for i in 1..6 {
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![i])));
+    }
+    assert_eq!(poll_once(|| duplex.poll()), Ok(Async::NotReady));
+}
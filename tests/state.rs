@@ -0,0 +1,193 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use futures::{Future, Sink, Stream};
+
+use dialogue::{Client, Dialogue, InSubDuplex, OutSubDuplex, PacketType, Response, Server,
+               SubDuplex};
+use dialogue::testing::{paired_transports, ConcretePacket, Never, PairedTransport, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+type Transport = PairedTransport<ConcretePacket>;
+type ClientDuplex<'a> = SubDuplex<'a, ConcretePacket, Transport, Never, Never, Vec<u8>, Client,
+                                  OutSubDuplex>;
+type ServerDuplex<'a> = SubDuplex<'a, ConcretePacket, Transport, Never, Never, Vec<u8>, Server,
+                                  InSubDuplex>;
+type ClientResponse<'a> = Response<'a, ConcretePacket, Transport, Never, Never, Vec<u8>, Client>;
+
+// Each transition is followed by the expected `(is_closed, is_closing)` of the
+// client and of the server.
+type DialogueStep = (&'static str,
+                     fn(&mut TestDialogue<Client>, &mut TestDialogue<Server>),
+                     (bool, bool),
+                     (bool, bool));
+
+fn check_dialogue_steps(steps: &[DialogueStep]) {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    for &(name, transition, expected_client, expected_server) in steps {
+        transition(&mut client, &mut server);
+        assert_eq!((client.is_closed(), client.is_closing()),
+                   expected_client,
+                   "client after: {}",
+                   name);
+        assert_eq!((server.is_closed(), server.is_closing()),
+                   expected_server,
+                   "server after: {}",
+                   name);
+    }
+}
+
+#[test]
+fn dialogue_states_while_closing() {
+    check_dialogue_steps(&[("nothing", |_, _| {}, (false, false), (false, false)),
+                           ("server asks to close",
+                            |_, server| {
+                                assert!(poll_once(|| server.close()).unwrap().is_not_ready());
+                            },
+                            (false, false),
+                            (false, false)),
+                           ("client sends close",
+                            |client, _| {
+                                assert!(poll_once(|| client.close()).unwrap().is_not_ready());
+                                assert!(client.is_close_requested());
+                            },
+                            (false, true),
+                            (false, false)),
+                           ("server reads close",
+                            |_, server| assert_eq!(run(|| server.poll()).unwrap(), None),
+                            (false, true),
+                            (false, true)),
+                           ("server answers close",
+                            |_, server| run(|| server.close()).unwrap(),
+                            (false, true),
+                            (true, false)),
+                           ("client reads answer",
+                            |client, _| run(|| client.close()).unwrap(),
+                            (true, false),
+                            (true, false))]);
+}
+
+#[test]
+fn dialogue_states_while_aborting() {
+    check_dialogue_steps(&[("nothing", |_, _| {}, (false, false), (false, false)),
+                           ("client aborts",
+                            |client, _| run(|| client.abort()).unwrap(),
+                            (true, false),
+                            (false, false)),
+                           ("server reads abort",
+                            |_, server| {
+                                let _ = run(|| server.poll());
+                                assert!(server.is_aborted_by_peer());
+                            },
+                            (true, false),
+                            (true, false))]);
+}
+
+// Each transition is followed by the expected `(is_half_closed, is_ended)` of
+// the initiating and of the accepting duplex.
+type DuplexStep = (&'static str,
+                   fn(&mut ClientDuplex, &mut ServerDuplex),
+                   (bool, bool),
+                   (bool, bool));
+
+fn check_duplex_steps(steps: &[DuplexStep]) {
+    let (a, b) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    let mut initiated = client.sub_duplex(vec![0]).unwrap();
+    run(|| Sink::poll_complete(&mut initiated)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+
+    for &(name, transition, expected_initiated, expected_accepted) in steps {
+        transition(&mut initiated, &mut accepted);
+        assert_eq!((initiated.is_half_closed(), initiated.is_ended()),
+                   expected_initiated,
+                   "initiated duplex after: {}",
+                   name);
+        assert_eq!((accepted.is_half_closed(), accepted.is_ended()),
+                   expected_accepted,
+                   "accepted duplex after: {}",
+                   name);
+    }
+}
+
+#[test]
+fn duplex_states_while_closing() {
+    check_duplex_steps(&[("nothing", |_, _| {}, (false, false), (false, false)),
+                         ("initiator closes",
+                          |initiated, _| {
+                              assert!(poll_once(|| Sink::close(initiated))
+                                          .unwrap()
+                                          .is_not_ready());
+                          },
+                          (true, false),
+                          (false, false)),
+                         ("acceptor reads end",
+                          |_, accepted| assert_eq!(run(|| accepted.poll()), Ok(None)),
+                          (true, false),
+                          (true, false)),
+                         ("acceptor closes",
+                          |_, accepted| run(|| Sink::close(accepted)).unwrap(),
+                          (true, false),
+                          (false, true)),
+                         ("initiator reads end",
+                          |initiated, _| run(|| Sink::close(initiated)).unwrap(),
+                          (false, true),
+                          (false, true))]);
+}
+
+#[test]
+fn duplex_states_while_aborting() {
+    check_duplex_steps(&[("nothing", |_, _| {}, (false, false), (false, false)),
+                         ("initiator aborts",
+                          |initiated, _| run(|| initiated.abort()).unwrap(),
+                          (false, true),
+                          (false, false)),
+                         ("acceptor reads end",
+                          |_, accepted| assert_eq!(run(|| accepted.poll()), Ok(None)),
+                          (false, true),
+                          (true, false)),
+                         ("acceptor closes",
+                          |_, accepted| run(|| Sink::close(accepted)).unwrap(),
+                          (false, true),
+                          (false, true))]);
+}
+
+// Each transition is followed by the expected `is_resolved` of the response.
+type ResponseStep = (&'static str, fn(&mut ClientResponse, &mut Transport), bool);
+
+#[test]
+fn response_states() {
+    let steps: &[ResponseStep] =
+        &[("request sent",
+           |response, _| run(|| response.poll_complete()).unwrap(),
+           false),
+          ("polled",
+           |response, _| assert!(poll_once(|| response.poll()).unwrap().is_not_ready()),
+           false),
+          ("response arrives",
+           |_, raw| {
+               raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+           },
+           false),
+          ("resolved",
+           |response, _| assert_eq!(run(|| response.poll()).unwrap(), Some(vec![1])),
+           true)];
+
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let mut response = client.request(vec![0]).unwrap();
+
+    for &(name, transition, expected) in steps {
+        transition(&mut response, &mut raw);
+        assert_eq!(response.is_resolved(), expected, "response after: {}", name);
+    }
+}
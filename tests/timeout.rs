@@ -0,0 +1,154 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll, Sink, Stream};
+
+use dialogue::{Dialogue, PacketType, Timer, TimedResponseError};
+use dialogue::testing::{paired_transports, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+/// A timer over a clock which only advances when told to.
+struct MockTimer {
+    now: Rc<Cell<Instant>>,
+}
+
+impl MockTimer {
+    fn new() -> MockTimer {
+        MockTimer { now: Rc::new(Cell::new(Instant::now())) }
+    }
+
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+struct MockDelay {
+    now: Rc<Cell<Instant>>,
+    deadline: Instant,
+}
+
+impl Future for MockDelay {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        if self.now.get() >= self.deadline {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Timer for MockTimer {
+    type Delay = MockDelay;
+
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn delay_until(&self, deadline: Instant) -> MockDelay {
+        MockDelay {
+            now: self.now.clone(),
+            deadline,
+        }
+    }
+}
+
+#[test]
+fn request_is_cancelled_at_the_deadline() {
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let timer = MockTimer::new();
+    let deadline = timer.now() + Duration::from_secs(30);
+
+    {
+        let mut response = client.request(vec![0]).unwrap().with_deadline(&timer, deadline);
+        assert!(poll_once(|| response.poll()).unwrap().is_not_ready());
+
+        timer.advance(Duration::from_secs(29));
+        assert!(poll_once(|| response.poll()).unwrap().is_not_ready());
+
+        timer.advance(Duration::from_secs(1));
+        match poll_once(|| response.poll()) {
+            Err(TimedResponseError::Timeout(timeout)) => assert_eq!(timeout.id(), 1),
+            _ => panic!("expected a timeout"),
+        }
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(Some(&[0]), 1, PacketType::Request)));
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(None, 1, PacketType::Request)));
+    assert_eq!(client.active_ids(), 0);
+}
+
+#[test]
+fn response_before_the_deadline_is_received() {
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let timer = MockTimer::new();
+    let deadline = timer.now() + Duration::from_secs(30);
+
+    let mut response = client.request(vec![0]).unwrap().with_deadline(&timer, deadline);
+    timer.advance(Duration::from_secs(10));
+    raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+
+    match run(|| response.poll()) {
+        Ok(data) => assert_eq!(data, Some(vec![1])),
+        Err(_) => panic!("expected the response"),
+    }
+}
+
+#[test]
+fn default_request_timeout_applies_to_timed_requests() {
+    let (a, mut raw) = paired_transports();
+    let mut client: TestDialogue<_> = Dialogue::client(a);
+    let timer = MockTimer::new();
+
+    {
+        // Without a default, a timed request waits for the peer.
+        let mut response = client.timed_request(vec![0], &timer).unwrap();
+        timer.advance(Duration::from_secs(3600));
+        assert!(poll_once(|| response.poll()).unwrap().is_not_ready());
+    }
+
+    client.set_default_request_timeout(Duration::from_secs(30));
+    {
+        let mut response = client.timed_request(vec![1], &timer).unwrap();
+        timer.advance(Duration::from_secs(29));
+        assert!(poll_once(|| response.poll()).unwrap().is_not_ready());
+
+        timer.advance(Duration::from_secs(1));
+        match poll_once(|| response.poll()) {
+            Err(TimedResponseError::Timeout(timeout)) => assert_eq!(timeout.id(), 3),
+            _ => panic!("expected a timeout"),
+        }
+        // The timeout is reported again when polling once more.
+        match poll_once(|| response.poll()) {
+            Err(TimedResponseError::Timeout(timeout)) => assert_eq!(timeout.id(), 3),
+            _ => panic!("expected a timeout"),
+        }
+    }
+    run(|| client.poll_complete()).unwrap();
+
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(Some(&[0]), 1, PacketType::Request)));
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(Some(&[1]), 3, PacketType::Request)));
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(None, 3, PacketType::Request)));
+    assert_eq!(client.active_ids(), 0);
+}
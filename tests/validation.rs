@@ -0,0 +1,152 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::fmt;
+
+use futures::{Async, Future, Sink, Stream};
+
+use dialogue::{Dialogue, PacketId, PacketReadable, PacketType, PacketValidator, SchemaValidator,
+               TransportError, ValidationError};
+use dialogue::testing::{paired_transports, ConcretePacket, Never, TestDialogue};
+
+use common::{run, poll_once, packet};
+
+#[test]
+fn empty_duplex_initial_is_rejected_by_the_schema() {
+    let (mut raw, b) = paired_transports();
+    let server: TestDialogue<_> = Dialogue::server(b);
+    let mut server = server.with_packet_validation(SchemaValidator);
+
+    raw.start_send(packet(None, 1, PacketType::DuplexInitial)).unwrap();
+    raw.start_send(packet(Some(&[7]), 3, PacketType::Message)).unwrap();
+
+    match run(|| server.poll()) {
+        Err(TransportError::ValidationError(err)) => {
+            assert_eq!(err.id, 1);
+            assert_eq!(err.packet_type, PacketType::DuplexInitial);
+        }
+        other => panic!("expected a ValidationError, got {:?}", other),
+    }
+
+    // The stream continues after a rejected packet.
+    let next = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(next.get_data(), Some(vec![7]));
+}
+
+#[test]
+fn rejected_duplex_is_ended_and_its_route_removed() {
+    let (mut raw, b) = paired_transports();
+    let server: TestDialogue<_> = Dialogue::server(b);
+    let mut server = server.with_packet_validation(SchemaValidator);
+
+    raw.start_send(packet(None, 1, PacketType::DuplexInitial)).unwrap();
+    assert!(run(|| server.poll()).is_err());
+
+    // The peer is told right away that the duplex has ended.
+    run(|| server.get_mut().poll_complete()).unwrap();
+    assert_eq!(run(|| raw.poll()).unwrap(),
+               Some(packet(None, 1, PacketType::DuplexResponseEnd)));
+
+    // Without a route, the id may be used for a new duplex.
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(initial.get_type(), PacketType::DuplexInitial);
+    assert_eq!(initial.get_data(), Some(vec![0]));
+}
+
+#[test]
+fn empty_duplex_initial_is_emitted_without_validation() {
+    let (mut raw, b) = paired_transports();
+    let mut server: TestDialogue<_> = Dialogue::server(b);
+
+    raw.start_send(packet(None, 1, PacketType::DuplexInitial)).unwrap();
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    assert_eq!(initial.get_type(), PacketType::DuplexInitial);
+    assert!(initial.is_empty());
+}
+
+fn assert_rejected<T: fmt::Debug>(result: Result<T, TransportError<Never, Never>>,
+                                  id: PacketId,
+                                  packet_type: PacketType) {
+    match result {
+        Err(TransportError::ValidationError(err)) => {
+            assert_eq!(err.id, id);
+            assert_eq!(err.packet_type, packet_type);
+        }
+        other => panic!("expected a ValidationError, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty_duplex_request_is_rejected_by_the_schema() {
+    let (mut raw, b) = paired_transports();
+    let server: TestDialogue<_> = Dialogue::server(b);
+    let mut server = server.with_packet_validation(SchemaValidator);
+
+    raw.start_send(packet(Some(&[0]), 1, PacketType::DuplexInitial)).unwrap();
+    let initial = run(|| server.poll()).unwrap().unwrap();
+
+    raw.start_send(packet(None, 1, PacketType::DuplexRequest)).unwrap();
+    raw.start_send(packet(Some(&[1]), 1, PacketType::DuplexRequest)).unwrap();
+    {
+        let mut duplex = server.get_mut().packet_as_sub_duplex(initial);
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![1])));
+        assert_eq!(poll_once(|| duplex.poll()), Ok(Async::NotReady));
+    }
+
+    // The packet was validated although the duplex read it.
+    assert_rejected(run(|| server.poll()), 1, PacketType::DuplexRequest);
+}
+
+#[test]
+fn empty_duplex_response_is_rejected_by_the_schema() {
+    let (a, mut raw) = paired_transports();
+    let client: TestDialogue<_> = Dialogue::client(a);
+    let mut client = client.with_packet_validation(SchemaValidator);
+
+    raw.start_send(packet(None, 1, PacketType::DuplexResponse)).unwrap();
+    raw.start_send(packet(Some(&[1]), 1, PacketType::DuplexResponse)).unwrap();
+    {
+        let mut duplex = client.get_mut().sub_duplex(vec![0]).unwrap();
+        run(|| Sink::poll_complete(&mut duplex)).unwrap();
+        assert_eq!(run(|| duplex.poll()), Ok(Some(vec![1])));
+        assert_eq!(poll_once(|| duplex.poll()), Ok(Async::NotReady));
+    }
+
+    assert_rejected(run(|| client.poll()), 1, PacketType::DuplexResponse);
+}
+
+/// Rejects all responses.
+struct NoResponses;
+
+impl PacketValidator<ConcretePacket> for NoResponses {
+    fn validate(&self, packet: &ConcretePacket) -> Result<(), ValidationError> {
+        if packet.get_type() == PacketType::Response {
+            Err(ValidationError {
+                    id: packet.get_id(),
+                    packet_type: PacketType::Response,
+                    reason: "no responses".to_string(),
+                })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn rejected_response_is_not_received() {
+    let (a, mut raw) = paired_transports();
+    let client: TestDialogue<_> = Dialogue::client(a);
+    let mut client = client.with_packet_validation(NoResponses);
+
+    raw.start_send(packet(Some(&[1]), 1, PacketType::Response)).unwrap();
+    {
+        let mut response = client.get_mut().request(vec![0]).unwrap();
+        assert!(poll_once(|| response.poll()).unwrap().is_not_ready());
+    }
+
+    assert_rejected(run(|| client.poll()), 1, PacketType::Response);
+}
@@ -0,0 +1,62 @@
+extern crate futures;
+extern crate dialogue;
+
+mod common;
+
+use std::io::{self, Write};
+
+use futures::{Async, Poll, Stream};
+
+use dialogue::Dialogue;
+
+use common::{run, poll_once, bounded_transports, BoundedDialogue};
+
+/// Flushes the writer within a task, mapping `WouldBlock` to `NotReady`.
+fn flush<W: Write>(writer: &mut W) -> Poll<(), io::Error> {
+    poll_once(|| match writer.flush() {
+                  Ok(()) => Ok(Async::Ready(())),
+                  Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+                  Err(err) => Err(err),
+              })
+}
+
+#[test]
+fn bytes_written_after_would_block_are_flushed() {
+    let (a, b) = bounded_transports(2);
+    let mut client: BoundedDialogue<_> = Dialogue::client(a);
+    let mut server: BoundedDialogue<_> = Dialogue::server(b);
+
+    let mut duplex = client.sub_duplex(vec![0]).unwrap();
+    let mut writer = duplex.as_write();
+
+    writer.write_all(b"ab").unwrap();
+    assert!(flush(&mut writer).unwrap().is_ready());
+    // The channel is full after this item.
+    writer.write_all(b"cd").unwrap();
+    assert!(flush(&mut writer).unwrap().is_not_ready());
+    // The dialogue queues this item.
+    writer.write_all(b"ef").unwrap();
+    assert!(flush(&mut writer).unwrap().is_not_ready());
+    // The queue is full, so this item stays pending in the writer.
+    writer.write_all(b"gh").unwrap();
+    assert!(flush(&mut writer).unwrap().is_not_ready());
+    writer.write_all(b"ij").unwrap();
+
+    let initial = run(|| server.poll()).unwrap().unwrap();
+    let mut accepted = server.packet_as_sub_duplex(initial);
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        received.extend(run(|| accepted.poll()).unwrap().unwrap());
+    }
+
+    // A single flush passes both the pending item and the bytes written since
+    // to the transport, even though the channel is full again afterwards.
+    assert!(flush(&mut writer).unwrap().is_not_ready());
+    for _ in 0..3 {
+        match poll_once(|| accepted.poll()).unwrap() {
+            Async::Ready(Some(item)) => received.extend(item),
+            _ => panic!("expected an item"),
+        }
+    }
+    assert_eq!(received, b"abcdefghij".to_vec());
+}